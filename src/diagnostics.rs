@@ -0,0 +1,174 @@
+use crate::parser::Span;
+use crate::runtime::errors;
+use std::fmt;
+
+/// Identifies the kind of problem a syntax diagnostic reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    ExpectedToken,
+    UnexpectedToken,
+    UnexpectedCharacter,
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidAssignmentTarget,
+    MissingInitializer,
+    IllegalJump,
+    UndefinedLabel,
+    DuplicateLabel,
+    MixedNullish,
+    InvalidEscape,
+    UnterminatedTemplate,
+    InvalidNumber,
+    TooDeeplyNested,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::ExpectedToken => "E0001",
+            ErrorCode::UnexpectedToken => "E0002",
+            ErrorCode::UnexpectedCharacter => "E0003",
+            ErrorCode::UnterminatedString => "E0004",
+            ErrorCode::UnterminatedComment => "E0005",
+            ErrorCode::InvalidAssignmentTarget => "E0006",
+            ErrorCode::MissingInitializer => "E0007",
+            ErrorCode::IllegalJump => "E0008",
+            ErrorCode::UndefinedLabel => "E0009",
+            ErrorCode::DuplicateLabel => "E0010",
+            ErrorCode::MixedNullish => "E0011",
+            ErrorCode::InvalidEscape => "E0012",
+            ErrorCode::UnterminatedTemplate => "E0013",
+            ErrorCode::InvalidNumber => "E0014",
+            ErrorCode::TooDeeplyNested => "E0015",
+        }
+    }
+}
+
+/// A problem found while lexing or parsing.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(code: ErrorCode, message: impl Into<String>, span: Span) -> Self {
+        Self { code, message: message.into(), span }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.code.as_str(), self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Every diagnostic reported for a source file, in source order.
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(Diagnostic::to_string).collect();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// An error tagged with the source location it was raised at. The message is
+/// the wrapped error's, so displaying a located error reads the same as before.
+#[derive(Debug)]
+pub struct Located {
+    pub span: Span,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for Located {}
+
+/// Attaches `span` to an error unless it already carries a location. Errors
+/// are located as they bubble up, so the innermost node wins.
+pub fn locate(error: anyhow::Error, span: Span) -> anyhow::Error {
+    if error.is::<Located>() {
+        error
+    } else {
+        anyhow::Error::new(Located { span, error })
+    }
+}
+
+/// Formats an error for the terminal, with a code frame pointing at its
+/// location when it has one:
+///
+/// ```text
+/// Uncaught ReferenceError: Undefined variable: x
+///  --> script.zn:3:13
+///   |
+/// 3 | console.log(x)
+///   |             ^
+///     at <anonymous> (script.zn:3:13)
+/// ```
+///
+/// Syntax diagnostics are each rendered this way, with their code after `Error`.
+pub fn render(error: &anyhow::Error, source: &str, file_name: &str) -> String {
+    if let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() {
+        let frames: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                format!(
+                    "Error[{}]: {}\n{}",
+                    diagnostic.code.as_str(),
+                    diagnostic.message,
+                    code_frame(source, file_name, diagnostic.span)
+                )
+            })
+            .collect();
+        return frames.join("\n\n");
+    }
+
+    let Some(located) = error.downcast_ref::<Located>() else {
+        return format!("Error: {}", error);
+    };
+    let mut rendered = format!("{}\n{}", located.error, code_frame(source, file_name, located.span));
+    if let Some(trace) = errors::call_trace(&located.error) {
+        rendered.push('\n');
+        rendered.push_str(&trace);
+    }
+    rendered
+}
+
+/// The ` --> file:line:column` header and the underlined source line for `span`.
+pub fn code_frame(source: &str, file_name: &str, span: Span) -> String {
+    let line_text = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+    let gutter = " ".repeat(span.line.to_string().len());
+
+    // Keep tabs in the padding so the caret lines up with the source
+    let padding: String = line_text
+        .chars()
+        .take(span.column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = if span.end_line == span.line {
+        span.end_column.saturating_sub(span.column).max(1)
+    } else {
+        // Multi-line spans are underlined up to the end of their first line
+        (line_text.chars().count() + 1).saturating_sub(span.column).max(1)
+    };
+
+    format!(
+        "{gutter}--> {file}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {padding}{carets}",
+        file = file_name,
+        line = span.line,
+        column = span.column,
+        text = line_text,
+        carets = "^".repeat(width),
+    )
+}
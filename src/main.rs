@@ -1,19 +1,30 @@
 use anyhow::Result;
 use clap::{Arg, Command};
 use std::path::Path;
-use tokio;
 
+mod diagnostics;
 mod parser;
 mod runtime;
 mod package;
 
+use diagnostics::Diagnostics;
 use parser::lexer::Lexer;
 use parser::Parser;
 use runtime::ZanoRuntime;
 use package::PackageManager;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+// Room for the interpreter's recursion: each nested call or expression is
+// several native frames deep, and debug builds use far more than release
+pub(crate) const INTERPRETER_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+fn main() -> Result<()> {
+    let interpreter = std::thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(|| tokio::runtime::Runtime::new()?.block_on(run()))?;
+    interpreter.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+async fn run() -> Result<()> {
     let matches = Command::new("zano")
         .version("0.1.0")
         .about("A Node.js-like backend runtime built in Rust")
@@ -90,7 +101,7 @@ async fn main() -> Result<()> {
     if matches.get_flag("interactive") {
         run_repl(runtime).await?;
     } else if let Some(code) = matches.get_one::<String>("eval") {
-        execute_code(&runtime, code).await?;
+        run_source(&runtime, code, "<eval>").await;
     } else if let Some(file_path) = matches.get_one::<String>("file") {
         run_file(&runtime, file_path).await?;
     } else {
@@ -113,15 +124,25 @@ async fn run_file(runtime: &ZanoRuntime, file_path: &str) -> Result<()> {
     }
 
     let source = tokio::fs::read_to_string(file_path).await?;
-    execute_code(runtime, &source).await
+    run_source(runtime, &source, file_path).await;
+    Ok(())
+}
+
+// Runs a whole program, reporting any error with a code frame and exiting
+async fn run_source(runtime: &ZanoRuntime, source: &str, file_name: &str) {
+    runtime.set_file_name(file_name);
+    if let Err(e) = execute_code(runtime, source).await {
+        eprintln!("{}", diagnostics::render(&e, source, file_name));
+        std::process::exit(1);
+    }
 }
 
 async fn execute_code(runtime: &ZanoRuntime, source: &str) -> Result<()> {
     let mut lexer = Lexer::new(source.to_string());
-    let tokens = lexer.scan_tokens()?;
+    let tokens = lexer.scan_tokens().map_err(Diagnostics)?;
 
     let mut parser = Parser::new(tokens);
-    let statements = parser.parse()?;
+    let statements = parser.parse().map_err(Diagnostics)?;
 
     let result = runtime.execute(statements).await?;
     
@@ -137,6 +158,7 @@ async fn execute_code(runtime: &ZanoRuntime, source: &str) -> Result<()> {
 async fn run_repl(runtime: ZanoRuntime) -> Result<()> {
     println!("Zano REPL v0.1.0");
     println!("Type .exit to quit");
+    runtime.set_file_name("<repl>");
 
     loop {
         print!("> ");
@@ -158,7 +180,7 @@ async fn run_repl(runtime: ZanoRuntime) -> Result<()> {
 
                 match execute_code(&runtime, input).await {
                     Ok(_) => {},
-                    Err(e) => println!("Error: {}", e),
+                    Err(e) => println!("{}", diagnostics::render(&e, input, "<repl>")),
                 }
             }
             Err(e) => {
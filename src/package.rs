@@ -142,6 +142,7 @@ impl PackageManager {
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub async fn load_package(&self) -> Result<PackageJson> {
         let package_json_path = self.project_root.join("package.json");
         
@@ -153,6 +154,7 @@ impl PackageManager {
         Ok(serde_json::from_str(&content)?)
     }
 
+    #[allow(dead_code)]
     pub fn resolve_module(&self, module_name: &str) -> Option<PathBuf> {
         // First check built-in modules
         match module_name {
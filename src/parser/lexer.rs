@@ -1,35 +1,66 @@
-use super::{Token, TokenKind};
-use anyhow::Result;
+use super::{Span, Token, TokenKind};
+use crate::diagnostics::{Diagnostic, ErrorCode};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use unicode_ident::{is_xid_continue, is_xid_start};
+
+type Result<T> = std::result::Result<T, Diagnostic>;
 
 pub struct Lexer {
-    source: String,
+    // Decoded up front so that positions are character indices and every
+    // lookup is constant time
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    column: usize,
+    start_line: usize,
+    start_column: usize,
+    // Brace depth inside each open `${`, innermost last
+    template_braces: Vec<usize>,
 }
 
 impl Lexer {
     pub fn new(source: String) -> Self {
         Self {
-            source,
+            source: source.chars().collect(),
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            template_braces: Vec::new(),
         }
     }
     
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>> {
+    /// Scans the whole source. Scanning carries on past a bad token so every
+    /// lexical error is reported at once.
+    pub fn scan_tokens(&mut self) -> std::result::Result<Vec<Token>, Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+        
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token()?;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            if let Err(diagnostic) = self.scan_token() {
+                diagnostics.push(diagnostic);
+            }
+        }
+        
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
         }
         
+        self.start_line = self.line;
+        self.start_column = self.column;
         self.tokens.push(Token {
             kind: TokenKind::Eof,
             lexeme: String::new(),
-            line: self.line,
+            span: self.token_span(),
+            cooked: None,
         });
         
         Ok(self.tokens.clone())
@@ -39,28 +70,91 @@ impl Lexer {
         let c = self.advance();
         
         match c {
-            ' ' | '\r' | '\t' => {} // Ignore whitespace
-            '\n' => {
-                self.add_token(TokenKind::Newline);
-                self.line += 1;
-            }
+            // Ignore whitespace, including the Unicode space separators
+            ' ' | '\r' | '\t' | '\u{0B}' | '\u{0C}' | '\u{FEFF}' => {}
+            c if c != '\n' && c.is_whitespace() => {}
+            '\n' => self.add_token(TokenKind::Newline),
             '(' => self.add_token(TokenKind::LeftParen),
             ')' => self.add_token(TokenKind::RightParen),
-            '{' => self.add_token(TokenKind::LeftBrace),
-            '}' => self.add_token(TokenKind::RightBrace),
+            '{' => {
+                if let Some(depth) = self.template_braces.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenKind::LeftBrace);
+            }
+            '}' => {
+                // The brace closing a `${` resumes the template around it
+                if self.template_braces.last() == Some(&0) {
+                    self.template_braces.pop();
+                    self.template(TokenKind::TemplateTail, TokenKind::TemplateMiddle)?;
+                } else {
+                    if let Some(depth) = self.template_braces.last_mut() {
+                        *depth -= 1;
+                    }
+                    self.add_token(TokenKind::RightBrace);
+                }
+            }
             '[' => self.add_token(TokenKind::LeftBracket),
             ']' => self.add_token(TokenKind::RightBracket),
             ':' => self.add_token(TokenKind::Colon),
             ',' => self.add_token(TokenKind::Comma),
+            '.' if self.peek().is_ascii_digit() => self.number()?,
+            '.' if self.peek() == '.' && self.peek_next() == '.' => {
+                self.advance();
+                self.advance();
+                self.add_token(TokenKind::DotDotDot);
+            }
             '.' => self.add_token(TokenKind::Dot),
-            '-' => self.add_token(TokenKind::Minus),
-            '+' => self.add_token(TokenKind::Plus),
+            '-' => {
+                let kind = if self.match_char('-') {
+                    TokenKind::MinusMinus
+                } else if self.match_char('=') {
+                    TokenKind::MinusEqual
+                } else {
+                    TokenKind::Minus
+                };
+                self.add_token(kind);
+            }
+            '+' => {
+                let kind = if self.match_char('+') {
+                    TokenKind::PlusPlus
+                } else if self.match_char('=') {
+                    TokenKind::PlusEqual
+                } else {
+                    TokenKind::Plus
+                };
+                self.add_token(kind);
+            }
             ';' => self.add_token(TokenKind::Semicolon),
-            '*' => self.add_token(TokenKind::Star),
-            '%' => self.add_token(TokenKind::Percent),
+            '*' => {
+                let kind = if self.match_char('*') {
+                    if self.match_char('=') {
+                        TokenKind::StarStarEqual
+                    } else {
+                        TokenKind::StarStar
+                    }
+                } else if self.match_char('=') {
+                    TokenKind::StarEqual
+                } else {
+                    TokenKind::Star
+                };
+                self.add_token(kind);
+            }
+            '%' => {
+                let kind = if self.match_char('=') {
+                    TokenKind::PercentEqual
+                } else {
+                    TokenKind::Percent
+                };
+                self.add_token(kind);
+            }
             '!' => {
                 let kind = if self.match_char('=') {
-                    TokenKind::BangEqual
+                    if self.match_char('=') {
+                        TokenKind::BangEqualEqual
+                    } else {
+                        TokenKind::BangEqual
+                    }
                 } else {
                     TokenKind::Bang
                 };
@@ -68,14 +162,26 @@ impl Lexer {
             }
             '=' => {
                 let kind = if self.match_char('=') {
-                    TokenKind::EqualEqual
+                    if self.match_char('=') {
+                        TokenKind::EqualEqualEqual
+                    } else {
+                        TokenKind::EqualEqual
+                    }
+                } else if self.match_char('>') {
+                    TokenKind::Arrow
                 } else {
                     TokenKind::Equal
                 };
                 self.add_token(kind);
             }
             '<' => {
-                let kind = if self.match_char('=') {
+                let kind = if self.match_char('<') {
+                    if self.match_char('=') {
+                        TokenKind::LessLessEqual
+                    } else {
+                        TokenKind::LessLess
+                    }
+                } else if self.match_char('=') {
                     TokenKind::LessEqual
                 } else {
                     TokenKind::Less
@@ -83,7 +189,19 @@ impl Lexer {
                 self.add_token(kind);
             }
             '>' => {
-                let kind = if self.match_char('=') {
+                let kind = if self.match_char('>') {
+                    if self.match_char('>') {
+                        if self.match_char('=') {
+                            TokenKind::GreaterGreaterGreaterEqual
+                        } else {
+                            TokenKind::GreaterGreaterGreater
+                        }
+                    } else if self.match_char('=') {
+                        TokenKind::GreaterGreaterEqual
+                    } else {
+                        TokenKind::GreaterGreater
+                    }
+                } else if self.match_char('=') {
                     TokenKind::GreaterEqual
                 } else {
                     TokenKind::Greater
@@ -91,14 +209,57 @@ impl Lexer {
                 self.add_token(kind);
             }
             '&' => {
-                if self.match_char('&') {
-                    self.add_token(TokenKind::AndAnd);
-                }
+                let kind = if self.match_char('&') {
+                    if self.match_char('=') {
+                        TokenKind::AndAndEqual
+                    } else {
+                        TokenKind::AndAnd
+                    }
+                } else if self.match_char('=') {
+                    TokenKind::AmpEqual
+                } else {
+                    TokenKind::Amp
+                };
+                self.add_token(kind);
             }
             '|' => {
-                if self.match_char('|') {
-                    self.add_token(TokenKind::OrOr);
-                }
+                let kind = if self.match_char('|') {
+                    if self.match_char('=') {
+                        TokenKind::OrOrEqual
+                    } else {
+                        TokenKind::OrOr
+                    }
+                } else if self.match_char('=') {
+                    TokenKind::PipeEqual
+                } else {
+                    TokenKind::Pipe
+                };
+                self.add_token(kind);
+            }
+            '^' => {
+                let kind = if self.match_char('=') {
+                    TokenKind::CaretEqual
+                } else {
+                    TokenKind::Caret
+                };
+                self.add_token(kind);
+            }
+            '~' => self.add_token(TokenKind::Tilde),
+            '?' => {
+                let kind = if self.match_char('?') {
+                    if self.match_char('=') {
+                        TokenKind::QuestionQuestionEqual
+                    } else {
+                        TokenKind::QuestionQuestion
+                    }
+                } else if self.peek() == '.' && !self.peek_next().is_ascii_digit() {
+                    // `a?.5:b` is a conditional, not optional chaining
+                    self.advance();
+                    TokenKind::QuestionDot
+                } else {
+                    TokenKind::Question
+                };
+                self.add_token(kind);
             }
             '/' => {
                 if self.match_char('/') {
@@ -109,19 +270,21 @@ impl Lexer {
                 } else if self.match_char('*') {
                     // Block comment
                     self.block_comment()?;
+                } else if self.match_char('=') {
+                    self.add_token(TokenKind::SlashEqual);
                 } else {
                     self.add_token(TokenKind::Slash);
                 }
             }
-            '"' => self.string()?,
-            '\'' => self.string_single()?,
+            '"' | '\'' => self.string(c)?,
+            '`' => self.template(TokenKind::TemplateString, TokenKind::TemplateHead)?,
             _ => {
                 if c.is_ascii_digit() {
                     self.number()?;
-                } else if c.is_ascii_alphabetic() || c == '_' {
+                } else if Self::is_identifier_start(c) {
                     self.identifier();
                 } else {
-                    return Err(anyhow::anyhow!("Unexpected character: {}", c));
+                    return Err(self.error(ErrorCode::UnexpectedCharacter, format!("Unexpected character: {}", c)));
                 }
             }
         }
@@ -129,78 +292,280 @@ impl Lexer {
         Ok(())
     }
     
-    fn string(&mut self) -> Result<()> {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    fn string(&mut self, quote: char) -> Result<()> {
+        // Built from UTF-16 units so escaped surrogate pairs combine correctly
+        let mut units = Vec::new();
+        let mut first_error = None;
+        
+        // A string cannot run past the end of its line
+        while self.peek() != quote && self.peek() != '\n' && !self.is_at_end() {
+            let c = self.advance();
+            if c != '\\' {
+                units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                continue;
+            }
+            
+            // Keep going after a bad escape so the rest of the string is skipped
+            if let Err(diagnostic) = self.escape_sequence(&mut units) {
+                first_error.get_or_insert(diagnostic);
             }
-            self.advance();
         }
         
-        if self.is_at_end() {
-            return Err(anyhow::anyhow!("Unterminated string"));
+        if self.peek() != quote {
+            return Err(self.error(ErrorCode::UnterminatedString, "Unterminated string"));
         }
         
-        // Closing "
+        // Closing quote
         self.advance();
         
-        // Trim quotes
-        let value = self.source[self.start + 1..self.current - 1].to_string();
-        self.add_token_with_literal(TokenKind::String, value);
+        if let Some(diagnostic) = first_error {
+            return Err(diagnostic);
+        }
+        self.add_token_with_literal(TokenKind::String, String::from_utf16_lossy(&units));
         
         Ok(())
     }
     
-    fn string_single(&mut self) -> Result<()> {
-        while self.peek() != '\'' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    /// Scans template text up to the closing backtick, which makes an `end`
+    /// token, or up to a `${`, which makes a `substitution` token. The lexeme
+    /// is the raw text; the cooked text has escapes decoded, and is `None` when
+    /// an escape is invalid so that tagged templates can still see the raw text.
+    fn template(&mut self, end: TokenKind, substitution: TokenKind) -> Result<()> {
+        let mut raw = String::new();
+        let mut units = Vec::new();
+        let mut valid = true;
+        
+        let kind = loop {
+            if self.is_at_end() {
+                return Err(self.error(ErrorCode::UnterminatedTemplate, "Unterminated template literal"));
             }
+            
+            let c = self.advance();
+            match c {
+                '`' => break end,
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.template_braces.push(0);
+                    break substitution;
+                }
+                '\\' if !self.is_at_end() => {
+                    let escape_start = self.current - 1;
+                    valid &= self.escape_sequence(&mut units).is_ok();
+                    raw.extend(&self.source[escape_start..self.current]);
+                }
+                // Line breaks are normalized to \n in both the cooked and raw text
+                '\r' => {
+                    self.match_line_feed();
+                    raw.push('\n');
+                    units.push('\n' as u16);
+                }
+                _ => {
+                    raw.push(c);
+                    units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                }
+            }
+        };
+        
+        let cooked = valid.then(|| String::from_utf16_lossy(&units));
+        self.tokens.push(Token {
+            kind,
+            lexeme: raw.replace("\r\n", "\n").replace('\r', "\n"),
+            span: self.token_span(),
+            cooked,
+        });
+        Ok(())
+    }
+    
+    // Decodes the escape sequence after a backslash into `units`
+    fn escape_sequence(&mut self, units: &mut Vec<u16>) -> Result<()> {
+        let (line, column) = (self.line, self.column - 1);
+        let escaped = self.advance();
+        
+        let code_point = match escaped {
+            'n' => '\n' as u32,
+            't' => '\t' as u32,
+            'r' => '\r' as u32,
+            'b' => 0x08,
+            'f' => 0x0C,
+            'v' => 0x0B,
+            '0' if !self.peek().is_ascii_digit() => 0,
+            '0'..='7' => {
+                return Err(self.error_since(line, column, ErrorCode::InvalidEscape, "Octal escape sequences are not allowed"));
+            }
+            'x' => match self.hex_digits(2) {
+                Some(value) => value,
+                None => {
+                    return Err(self.error_since(line, column, ErrorCode::InvalidEscape, "Invalid hexadecimal escape sequence"));
+                }
+            },
+            'u' => self.unicode_escape(line, column)?,
+            // Line continuation: the backslash and line break are dropped
+            '\r' => {
+                self.match_line_feed();
+                return Ok(());
+            }
+            '\n' => return Ok(()),
+            '\0' if self.current > self.source.len() => {
+                return Err(self.error(ErrorCode::UnterminatedString, "Unterminated string"));
+            }
+            // Any other character, including quotes and backslashes, stands for itself
+            other => other as u32,
+        };
+        
+        match char::from_u32(code_point) {
+            Some(c) => units.extend(c.encode_utf16(&mut [0; 2]).iter()),
+            // A lone surrogate from \uD800-\uDFFF; pairs are combined when decoded
+            None => units.push(code_point as u16),
+        }
+        Ok(())
+    }
+    
+    // The rest of a \uHHHH or \u{H...} escape
+    fn unicode_escape(&mut self, line: usize, column: usize) -> Result<u32> {
+        if !self.match_char('{') {
+            return self
+                .hex_digits(4)
+                .ok_or_else(|| self.error_since(line, column, ErrorCode::InvalidEscape, "Invalid Unicode escape sequence"));
+        }
+        
+        let mut value: u32 = 0;
+        let mut digits = 0;
+        while let Some(digit) = self.peek().to_digit(16) {
             self.advance();
+            value = value.saturating_mul(16).saturating_add(digit);
+            digits += 1;
+        }
+        if digits == 0 || !self.match_char('}') {
+            return Err(self.error_since(line, column, ErrorCode::InvalidEscape, "Invalid Unicode escape sequence"));
+        }
+        if value > 0x10FFFF {
+            return Err(self.error_since(line, column, ErrorCode::InvalidEscape, "Undefined Unicode code-point"));
+        }
+        Ok(value)
+    }
+    
+    // Consumes exactly `count` hex digits, or nothing if they are not all there
+    fn hex_digits(&mut self, count: usize) -> Option<u32> {
+        let digits: String = self.source.iter().skip(self.current).take(count).collect();
+        if digits.len() != count || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
         }
+        for _ in 0..count {
+            self.advance();
+        }
+        u32::from_str_radix(&digits, 16).ok()
+    }
+    
+    /// Scans a numeric literal whose first character (a digit, or the `.` of
+    /// `.5`) was just consumed. Number tokens get a lexeme that Rust's `f64`
+    /// parser accepts, and BigInt tokens their value in decimal digits.
+    fn number(&mut self) -> Result<()> {
+        let first = self.source[self.start];
+        let radix = match (first, self.peek()) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'o' | 'O') => 8,
+            ('0', 'b' | 'B') => 2,
+            _ => 10,
+        };
         
-        if self.is_at_end() {
-            return Err(anyhow::anyhow!("Unterminated string"));
+        if radix != 10 {
+            let prefix = format!("0{}", self.advance());
+            if self.digits(radix)? == 0 {
+                return Err(self.error(ErrorCode::InvalidNumber, format!("Expected digits after '{}'", prefix)));
+            }
+            let digits: String = self.source[self.start + 2..self.current].iter().filter(|&&c| c != '_').collect();
+            let value = BigInt::parse_bytes(digits.as_bytes(), radix).unwrap_or_default();
+            return if self.match_char('n') {
+                self.finish_number(TokenKind::BigInt, value.to_string())
+            } else {
+                let value = value.to_f64().unwrap_or(f64::INFINITY);
+                self.finish_number(TokenKind::Number, value.to_string())
+            };
         }
         
-        // Closing '
-        self.advance();
+        if first == '0' && self.peek().is_ascii_digit() {
+            self.digits(10)?;
+            return Err(self.error(ErrorCode::InvalidNumber, "Legacy octal literals are not allowed; use the '0o' prefix"));
+        }
         
-        // Trim quotes
-        let value = self.source[self.start + 1..self.current - 1].to_string();
-        self.add_token_with_literal(TokenKind::String, value);
+        let mut is_integer = first != '.';
+        if is_integer {
+            self.digits(10)?;
+            if self.match_char('.') {
+                is_integer = false;
+                self.digits(10)?;
+            }
+        } else {
+            self.digits(10)?;
+        }
         
-        Ok(())
+        if matches!(self.peek(), 'e' | 'E') {
+            is_integer = false;
+            self.advance();
+            if !self.match_char('+') {
+                self.match_char('-');
+            }
+            if self.digits(10)? == 0 {
+                return Err(self.error(ErrorCode::InvalidNumber, "Exponent has no digits"));
+            }
+        }
+        
+        let text: String = self.lexeme().chars().filter(|&c| c != '_').collect();
+        if self.match_char('n') {
+            if !is_integer {
+                return Err(self.error(ErrorCode::InvalidNumber, "Invalid BigInt literal"));
+            }
+            return self.finish_number(TokenKind::BigInt, text);
+        }
+        self.finish_number(TokenKind::Number, text)
     }
     
-    fn number(&mut self) -> Result<()> {
-        while self.peek().is_ascii_digit() {
+    // Consumes digits in `radix` and the separators between them, returning
+    // how many digits there were
+    fn digits(&mut self, radix: u32) -> Result<usize> {
+        let mut count = 0;
+        loop {
+            let c = self.peek();
+            if c.is_digit(radix) {
+                count += 1;
+            } else if c == '_' {
+                let previous = self.source[self.current - 1];
+                if !previous.is_digit(radix) || !self.peek_next().is_digit(radix) {
+                    self.advance();
+                    return Err(self.error(ErrorCode::InvalidNumber, "Numeric separators are only allowed between digits"));
+                }
+            } else {
+                return Ok(count);
+            }
             self.advance();
         }
-        
-        // Look for decimal part
-        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
-            // Consume '.'
+    }
+    
+    // A number must not run straight into a digit it cannot hold or a name
+    fn finish_number(&mut self, kind: TokenKind, lexeme: String) -> Result<()> {
+        let next = self.peek();
+        if next.is_ascii_digit() {
             self.advance();
-            
-            while self.peek().is_ascii_digit() {
+            return Err(self.error(ErrorCode::InvalidNumber, format!("Invalid digit '{}' in numeric literal", next)));
+        }
+        if Self::is_identifier_start(next) || next == '\\' {
+            while Self::is_identifier_part(self.peek()) {
                 self.advance();
             }
+            return Err(self.error(ErrorCode::InvalidNumber, "Identifier starts immediately after numeric literal"));
         }
         
-        let value = self.source[self.start..self.current].to_string();
-        self.add_token_with_literal(TokenKind::Number, value);
-        
+        self.add_token_with_literal(kind, lexeme);
         Ok(())
     }
     
     fn identifier(&mut self) {
-        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' {
+        while Self::is_identifier_part(self.peek()) {
             self.advance();
         }
         
-        let text = &self.source[self.start..self.current];
-        let kind = match text {
+        let text = self.lexeme();
+        let kind = match text.as_str() {
             "let" => TokenKind::Let,
             "const" => TokenKind::Const,
             "var" => TokenKind::Var,
@@ -208,12 +573,27 @@ impl Lexer {
             "if" => TokenKind::If,
             "else" => TokenKind::Else,
             "while" => TokenKind::While,
+            "do" => TokenKind::Do,
+            "switch" => TokenKind::Switch,
+            "case" => TokenKind::Case,
+            "default" => TokenKind::Default,
+            "for" => TokenKind::For,
+            "in" => TokenKind::In,
+            "break" => TokenKind::Break,
+            "continue" => TokenKind::Continue,
             "return" => TokenKind::Return,
             "async" => TokenKind::Async,
             "await" => TokenKind::Await,
             "try" => TokenKind::Try,
             "catch" => TokenKind::Catch,
+            "finally" => TokenKind::Finally,
             "throw" => TokenKind::Throw,
+            "typeof" => TokenKind::Typeof,
+            "void" => TokenKind::Void,
+            "delete" => TokenKind::Delete,
+            "new" => TokenKind::New,
+            "this" => TokenKind::This,
+            "instanceof" => TokenKind::Instanceof,
             "true" | "false" => TokenKind::Boolean,
             "null" => TokenKind::Null,
             "undefined" => TokenKind::Undefined,
@@ -223,6 +603,16 @@ impl Lexer {
         self.add_token(kind);
     }
     
+    // IdentifierStart and IdentifierPart from the spec, which build on the
+    // Unicode ID_Start and ID_Continue properties
+    fn is_identifier_start(c: char) -> bool {
+        c == '$' || c == '_' || is_xid_start(c)
+    }
+    
+    fn is_identifier_part(c: char) -> bool {
+        c == '$' || c == '\u{200C}' || c == '\u{200D}' || is_xid_continue(c)
+    }
+    
     fn block_comment(&mut self) -> Result<()> {
         let mut depth = 1;
         
@@ -236,15 +626,12 @@ impl Lexer {
                 self.advance();
                 depth -= 1;
             } else {
-                if self.peek() == '\n' {
-                    self.line += 1;
-                }
                 self.advance();
             }
         }
         
         if depth > 0 {
-            return Err(anyhow::anyhow!("Unterminated block comment"));
+            return Err(self.error(ErrorCode::UnterminatedComment, "Unterminated block comment"));
         }
         
         Ok(())
@@ -255,42 +642,73 @@ impl Lexer {
             return false;
         }
         
-        if self.source.chars().nth(self.current) != Some(expected) {
+        if self.source[self.current] != expected {
             return false;
         }
         
         self.current += 1;
+        self.column += 1;
         true
     }
     
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current).unwrap_or('\0')
-        }
+        self.source.get(self.current).copied().unwrap_or('\0')
     }
     
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current + 1).unwrap_or('\0')
-        }
+        self.source.get(self.current + 1).copied().unwrap_or('\0')
     }
     
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
     
+    // Consumes the `\n` of a `\r\n` line break, counting the new line
+    fn match_line_feed(&mut self) {
+        if !self.is_at_end() && self.peek() == '\n' {
+            self.advance();
+        }
+    }
+    
     fn advance(&mut self) -> char {
-        let c = self.source.chars().nth(self.current).unwrap_or('\0');
+        let c = self.peek();
         self.current += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         c
     }
     
+    fn error(&self, code: ErrorCode, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(code, message, self.token_span())
+    }
+    
+    // An error covering from `line`/`column` up to the character being scanned
+    fn error_since(&self, line: usize, column: usize, code: ErrorCode, message: &str) -> Diagnostic {
+        let span = Span { line, column, end_line: self.line, end_column: self.column };
+        Diagnostic::new(code, message, span)
+    }
+    
+    // From the start of the current token up to the character being scanned
+    fn token_span(&self) -> Span {
+        Span {
+            line: self.start_line,
+            column: self.start_column,
+            end_line: self.line,
+            end_column: self.column,
+        }
+    }
+    
+    // The source text of the current token
+    fn lexeme(&self) -> String {
+        self.source[self.start..self.current].iter().collect()
+    }
+    
     fn add_token(&mut self, kind: TokenKind) {
-        let text = self.source[self.start..self.current].to_string();
+        let text = self.lexeme();
         self.add_token_with_literal(kind, text);
     }
     
@@ -298,7 +716,8 @@ impl Lexer {
         self.tokens.push(Token {
             kind,
             lexeme,
-            line: self.line,
+            span: self.token_span(),
+            cooked: None,
         });
     }
 }
\ No newline at end of file
@@ -1,32 +1,230 @@
+use crate::diagnostics::{Diagnostic, ErrorCode};
+use crate::runtime::ZanoFunction;
+use crate::runtime::errors::ErrorType;
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 pub mod lexer;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub enum ZanoValue {
     Undefined,
     Null,
     Boolean(bool),
     Number(f64),
+    BigInt(BigInt),
     String(String),
-    Object(std::collections::HashMap<String, ZanoValue>),
-    Array(Vec<ZanoValue>),
-    Function(String), // Function name/id for now
+    Object(Shared<ObjectData>),
+    Array(Shared<ArrayData>),
+    Function(Arc<dyn ZanoFunction>),
+}
+
+impl ZanoValue {
+    pub fn new_object(properties: IndexMap<String, ZanoValue>) -> Self {
+        ZanoValue::Object(Shared::new(ObjectData { properties, error_type: None }))
+    }
+    
+    pub fn new_array(items: Vec<ZanoValue>) -> Self {
+        let length = items.len();
+        let items = items.into_iter().map(Some).collect();
+        ZanoValue::Array(Shared::new(ArrayData { items, sparse: BTreeMap::new(), length, properties: IndexMap::new() }))
+    }
+}
+
+/// An object's properties. `error_type` marks objects made by the Error
+/// constructors, which print and convert to strings the way JS errors do,
+/// and records which constructor `instanceof` matches them against.
+pub struct ObjectData {
+    pub properties: IndexMap<String, ZanoValue>,
+    pub error_type: Option<ErrorType>,
+}
+
+impl std::fmt::Debug for ObjectData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.properties.fmt(f)
+    }
+}
+
+/// An array's elements, plus any named properties set on it such as the
+/// `raw` strings of a tagged template.
+///
+/// Indices below `length` with no element, whether skipped over by a write
+/// past the end, left by `delete` or added by growing `length`, are holes:
+/// they read as undefined but `for...in` skips them and the console prints
+/// them as empty items. Holes near the front are `None` in `items`; elements
+/// written far past the end are kept aside in `sparse`, so that `a[1e6] = 1`
+/// doesn't allocate a million slots.
+pub struct ArrayData {
+    items: Vec<Option<ZanoValue>>,
+    sparse: BTreeMap<usize, ZanoValue>,
+    length: usize,
+    pub properties: IndexMap<String, ZanoValue>,
+}
+
+impl ArrayData {
+    // How far past the end of `items` a write may land and still extend
+    // `items` rather than going to `sparse`
+    const MAX_GAP: usize = 1024;
+    
+    pub fn length(&self) -> usize {
+        self.length
+    }
+    
+    /// The element at `index`, or `None` for a hole.
+    pub fn get(&self, index: usize) -> Option<&ZanoValue> {
+        match self.items.get(index) {
+            Some(slot) => slot.as_ref(),
+            None => self.sparse.get(&index),
+        }
+    }
+    
+    pub fn set(&mut self, index: usize, value: ZanoValue) {
+        if index < self.items.len() {
+            self.items[index] = Some(value);
+        } else if index - self.items.len() <= Self::MAX_GAP {
+            while self.items.len() < index {
+                let slot = self.sparse.remove(&self.items.len());
+                self.items.push(slot);
+            }
+            self.sparse.remove(&index);
+            self.items.push(Some(value));
+            // Elements kept aside may now continue on from the end
+            while let Some(element) = self.sparse.remove(&self.items.len()) {
+                self.items.push(Some(element));
+            }
+        } else {
+            self.sparse.insert(index, value);
+        }
+        self.length = self.length.max(index + 1);
+    }
+    
+    /// Leaves a hole at `index`, as `delete` does.
+    pub fn delete(&mut self, index: usize) {
+        match self.items.get_mut(index) {
+            Some(slot) => *slot = None,
+            None => {
+                self.sparse.remove(&index);
+            }
+        }
+    }
+    
+    /// Sets `length`, dropping any elements past it. Growing only adds holes.
+    pub fn set_length(&mut self, length: usize) {
+        if length <= self.items.len() {
+            self.items.truncate(length);
+            self.sparse.clear();
+        } else {
+            self.sparse.split_off(&length);
+        }
+        self.length = length;
+    }
+    
+    /// The elements present, in index order, skipping holes.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, &ZanoValue)> {
+        let items = self.items.iter().enumerate().filter_map(|(index, slot)| Some((index, slot.as_ref()?)));
+        items.chain(self.sparse.iter().map(|(index, element)| (*index, element)))
+    }
+    
+    /// Every element up to `length`, with holes as undefined.
+    pub fn values(&self) -> Vec<ZanoValue> {
+        let mut values = vec![ZanoValue::Undefined; self.length];
+        for (index, element) in self.entries() {
+            values[index] = element.clone();
+        }
+        values
+    }
+}
+
+impl std::fmt::Debug for ArrayData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.values().fmt(f)
+    }
+}
+
+/// A heap value shared by every binding that refers to it, giving objects
+/// and arrays JavaScript's reference semantics. Guards must not be held
+/// across an `.await`.
+pub struct Shared<T>(Arc<RwLock<T>>);
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
+    
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    
+    /// Identity comparison, as used by `===` on objects.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    
+    /// The address behind this reference, for spotting cycles.
+    pub fn as_ptr(&self) -> *const () {
+        Arc::as_ptr(&self.0) as *const ()
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.read().fmt(f)
+    }
+}
+
+/// A region of source text. Lines and columns are 1-based, and the end is
+/// exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end_line: other.end_line,
+            end_column: other.end_column,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     Expression(Expression),
     VarDeclaration {
-        name: String,
+        target: Pattern,
         value: Option<Expression>,
-        is_const: bool,
+        kind: DeclarationKind,
     },
     FunctionDeclaration {
         name: String,
-        params: Vec<String>,
+        params: Vec<Parameter>,
         body: Vec<Statement>,
+        #[allow(dead_code)]
         is_async: bool,
     },
     If {
@@ -40,96 +238,390 @@ pub enum Statement {
         condition: Expression,
         body: Box<Statement>,
     },
+    DoWhile {
+        body: Box<Statement>,
+        condition: Expression,
+    },
+    For {
+        init: Option<Box<Statement>>,
+        condition: Option<Expression>,
+        update: Option<Expression>,
+        body: Box<Statement>,
+    },
+    ForOf {
+        declaration: Option<DeclarationKind>,
+        target: Pattern,
+        iterable: Expression,
+        body: Box<Statement>,
+    },
+    ForIn {
+        declaration: Option<DeclarationKind>,
+        target: Pattern,
+        object: Expression,
+        body: Box<Statement>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Labeled {
+        label: String,
+        body: Box<Statement>,
+    },
     Try {
         try_block: Box<Statement>,
-        catch_param: Option<String>,
+        catch_param: Option<Pattern>,
         catch_block: Option<Box<Statement>>,
+        finally_block: Option<Box<Statement>>,
     },
     Throw(Expression),
+    Switch {
+        discriminant: Expression,
+        cases: Vec<SwitchCase>,
+    },
+}
+
+/// A function parameter. `default` is evaluated at call time when the
+/// argument is undefined; a `rest` parameter collects the remaining arguments.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub target: Pattern,
+    pub default: Option<Expression>,
+    pub rest: bool,
+}
+
+/// What a declaration, parameter or catch clause binds: a name, or an object
+/// or array pattern that takes the value apart.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Identifier(String),
+    /// `{ key, key: target = default, ...rest }`
+    Object {
+        properties: Vec<(String, PatternElement)>,
+        rest: Option<String>,
+    },
+    /// `[first, , third = default, ...rest]`, where `None` is a hole
+    Array {
+        elements: Vec<Option<PatternElement>>,
+        rest: Option<Box<Pattern>>,
+    },
+    /// A member or index expression, which only assignment patterns contain
+    Target(Box<Expression>),
+}
+
+/// A target inside a destructuring pattern, with the default used when the
+/// value there is undefined.
+#[derive(Debug, Clone)]
+pub struct PatternElement {
+    pub target: Pattern,
+    pub default: Option<Expression>,
+}
+
+impl Pattern {
+    /// The names the pattern binds, in source order.
+    pub fn names(&self) -> Vec<String> {
+        match self {
+            Pattern::Identifier(name) => vec![name.clone()],
+            Pattern::Object { properties, rest } => properties
+                .iter()
+                .flat_map(|(_, element)| element.target.names())
+                .chain(rest.clone())
+                .collect(),
+            Pattern::Array { elements, rest } => elements
+                .iter()
+                .flatten()
+                .flat_map(|element| element.target.names())
+                .chain(rest.iter().flat_map(|rest| rest.names()))
+                .collect(),
+            Pattern::Target(_) => Vec::new(),
+        }
+    }
 }
 
+/// An entry in an object literal: `key: value`, or `...value` to copy the
+/// properties of another object.
 #[derive(Debug, Clone)]
-pub enum Expression {
+pub enum ObjectProperty {
+    KeyValue(String, Expression),
+    Spread(Expression),
+}
+
+/// A `case` clause, or the `default` clause when `test` is `None`.
+#[derive(Debug, Clone)]
+pub struct SwitchCase {
+    pub test: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclarationKind {
+    Var, Let, Const,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExpressionKind {
     Literal(ZanoValue),
     Identifier(String),
+    This,
+    Unary {
+        operator: UnaryOp,
+        operand: Box<Expression>,
+    },
     Binary {
         left: Box<Expression>,
         operator: BinaryOp,
         right: Box<Expression>,
     },
+    /// `optional` marks a `?.` link in an optional chain, as do the flags on
+    /// `Member` and `Index`.
     Call {
         callee: Box<Expression>,
         args: Vec<Expression>,
+        optional: bool,
     },
     Member {
         object: Box<Expression>,
         property: String,
+        optional: bool,
+    },
+    New {
+        callee: Box<Expression>,
+        args: Vec<Expression>,
     },
     Assignment {
-        target: String,
+        target: Box<Expression>,
+        value: Box<Expression>,
+    },
+    /// `[a, b] = value` or `({ a, b } = value)`, assigning to existing bindings
+    DestructuringAssignment {
+        target: Pattern,
+        value: Box<Expression>,
+    },
+    CompoundAssignment {
+        target: Box<Expression>,
+        operator: BinaryOp,
         value: Box<Expression>,
     },
+    Update {
+        operator: UpdateOp,
+        prefix: bool,
+        target: Box<Expression>,
+    },
     Array(Vec<Expression>),
-    Object(Vec<(String, Expression)>),
+    Object(Vec<ObjectProperty>),
+    /// `...value` in call arguments and array literals, which the parser
+    /// allows nowhere else.
+    Spread(Box<Expression>),
     Index {
         object: Box<Expression>,
         index: Box<Expression>,
+        optional: bool,
+    },
+    /// A chain containing `?.`. A `?.` on null or undefined ends evaluation of
+    /// the whole chain with undefined.
+    OptionalChain(Box<Expression>),
+    Conditional {
+        condition: Box<Expression>,
+        then_branch: Box<Expression>,
+        else_branch: Box<Expression>,
     },
     Await(Box<Expression>),
+    /// A function or arrow function expression. An arrow's expression body is
+    /// parsed as a single `return`.
+    Function {
+        name: Option<String>,
+        params: Vec<Parameter>,
+        body: Vec<Statement>,
+        is_arrow: bool,
+    },
+    /// An untagged template: `quasis` has one more entry than `expressions`.
+    Template {
+        quasis: Vec<String>,
+        expressions: Vec<Expression>,
+    },
+    TaggedTemplate {
+        tag: Box<Expression>,
+        cooked: Vec<Option<String>>,
+        raw: Vec<String>,
+        expressions: Vec<Expression>,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Not, Negate, Plus, BitNot, Typeof, Void, Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateOp {
+    Increment, Decrement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOp {
-    Add, Sub, Mul, Div, Mod,
-    Equal, NotEqual, Less, Greater, LessEqual, GreaterEqual,
-    And, Or,
+    Add, Sub, Mul, Div, Mod, Pow,
+    BitAnd, BitOr, BitXor, ShiftLeft, ShiftRight, UnsignedShiftRight,
+    Equal, NotEqual, StrictEqual, StrictNotEqual,
+    Less, Greater, LessEqual, GreaterEqual, Instanceof,
+    And, Or, Nullish,
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // Enclosing loops, switches and labels, used to validate break/continue
+    loop_depth: usize,
+    switch_depth: usize,
+    labels: Vec<(String, bool)>,
+    diagnostics: Vec<Diagnostic>,
+    // `{ key = value }` properties seen in object literals. They are only
+    // valid once the literal turns out to be an assignment pattern.
+    cover_initializers: Vec<Span>,
+    // The index of the bracket that closes each opening bracket, found in one
+    // pass so that looking ahead for `=>` doesn't rescan nested parentheses
+    closing_brackets: HashMap<usize, usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
-    pub line: usize,
+    pub span: Span,
+    /// For template tokens, the text with escapes decoded; `None` if one is invalid.
+    pub cooked: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     // Literals
-    Number, String, Boolean, Null, Undefined,
+    Number, BigInt, String, Boolean, Null, Undefined,
+    // A template without substitutions, or the text before, between and after them
+    TemplateString, TemplateHead, TemplateMiddle, TemplateTail,
     
     // Identifiers
     Identifier,
     
     // Keywords
-    Let, Const, Var, Function, If, Else, While, Return, Async, Await,
-    Try, Catch, Throw,
+    Let, Const, Var, Function, If, Else, While, Do, For, In, Break, Continue, Return, Async, Await,
+    Switch, Case, Default,
+    Try, Catch, Finally, Throw, Typeof, Void, Delete, New, This, Instanceof,
     
     // Operators
-    Plus, Minus, Star, Slash, Percent,
-    Equal, EqualEqual, Bang, BangEqual,
+    Plus, Minus, Star, StarStar, Slash, Percent, PlusPlus, MinusMinus,
+    PlusEqual, MinusEqual, StarEqual, StarStarEqual, SlashEqual, PercentEqual,
+    AndAndEqual, OrOrEqual, QuestionQuestionEqual,
+    Amp, Pipe, Caret, Tilde, LessLess, GreaterGreater, GreaterGreaterGreater,
+    AmpEqual, PipeEqual, CaretEqual, LessLessEqual, GreaterGreaterEqual, GreaterGreaterGreaterEqual,
+    Equal, EqualEqual, EqualEqualEqual, Bang, BangEqual, BangEqualEqual,
     Greater, GreaterEqual, Less, LessEqual,
-    AndAnd, OrOr,
+    AndAnd, OrOr, Question, QuestionQuestion, QuestionDot, Arrow,
     
     // Punctuation
     LeftParen, RightParen, LeftBrace, RightBrace,
-    LeftBracket, RightBracket, Colon,
+    LeftBracket, RightBracket, Colon, DotDotDot,
     Comma, Semicolon, Dot,
     
     // Special
     Eof, Newline,
 }
 
+impl Statement {
+    pub fn is_loop(&self) -> bool {
+        matches!(
+            self.kind,
+            StatementKind::While { .. } | StatementKind::DoWhile { .. } | StatementKind::For { .. }
+                | StatementKind::ForOf { .. } | StatementKind::ForIn { .. }
+        )
+    }
+    
+    /// Collects the `var` names declared here, including in nested blocks
+    /// and loops but not in nested functions, which get scopes of their own.
+    pub fn var_names(&self, names: &mut Vec<String>) {
+        match &self.kind {
+            StatementKind::VarDeclaration { target, kind: DeclarationKind::Var, .. } => names.extend(target.names()),
+            StatementKind::ForOf { declaration: Some(DeclarationKind::Var), target, body, .. }
+            | StatementKind::ForIn { declaration: Some(DeclarationKind::Var), target, body, .. } => {
+                names.extend(target.names());
+                body.var_names(names);
+            }
+            StatementKind::If { then_branch, else_branch, .. } => {
+                then_branch.var_names(names);
+                if let Some(else_branch) = else_branch {
+                    else_branch.var_names(names);
+                }
+            }
+            StatementKind::Block(statements) => statements.iter().for_each(|statement| statement.var_names(names)),
+            StatementKind::For { init, body, .. } => {
+                if let Some(init) = init {
+                    init.var_names(names);
+                }
+                body.var_names(names);
+            }
+            StatementKind::While { body, .. }
+            | StatementKind::DoWhile { body, .. }
+            | StatementKind::ForOf { body, .. }
+            | StatementKind::ForIn { body, .. }
+            | StatementKind::Labeled { body, .. } => body.var_names(names),
+            StatementKind::Try { try_block, catch_block, finally_block, .. } => {
+                try_block.var_names(names);
+                for block in [catch_block, finally_block].into_iter().flatten() {
+                    block.var_names(names);
+                }
+            }
+            StatementKind::Switch { cases, .. } => {
+                cases.iter().flat_map(|case| &case.body).for_each(|statement| statement.var_names(names));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        let closing_brackets = Self::match_brackets(&tokens);
+        Self {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            switch_depth: 0,
+            labels: Vec::new(),
+            diagnostics: Vec::new(),
+            cover_initializers: Vec::new(),
+            closing_brackets,
+        }
     }
     
-    pub fn parse(&mut self) -> Result<Vec<Statement>> {
+    fn match_brackets(tokens: &[Token]) -> HashMap<usize, usize> {
+        let mut closing = HashMap::new();
+        let mut open = Vec::new();
+        for (i, token) in tokens.iter().enumerate() {
+            match Self::bracket_delta(&token.kind) {
+                1 => open.push(i),
+                -1 => {
+                    if let Some(start) = open.pop() {
+                        closing.insert(start, i);
+                    }
+                }
+                _ => {}
+            }
+        }
+        closing
+    }
+    
+    /// Parses the whole program. A syntax error does not stop parsing: the
+    /// parser records it, skips to the next statement and carries on, so every
+    /// error in the file is returned together.
+    pub fn parse(&mut self) -> std::result::Result<Vec<Statement>, Vec<Diagnostic>> {
         let mut statements = Vec::new();
         
         while !self.is_at_end() {
@@ -137,13 +629,146 @@ impl Parser {
                 self.advance();
                 continue;
             }
-            statements.push(self.statement()?);
+            if let Some(statement) = self.recovering_statement(false) {
+                statements.push(statement);
+            }
         }
         
-        Ok(statements)
+        if self.diagnostics.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+    
+    // Parses a statement, or records its error and skips past it
+    fn recovering_statement(&mut self, in_block: bool) -> Option<Statement> {
+        let start = self.current;
+        match self.statement() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                let diagnostic = match error.downcast::<Diagnostic>() {
+                    Ok(diagnostic) => diagnostic,
+                    Err(error) => Diagnostic::new(ErrorCode::UnexpectedToken, error.to_string(), self.peek().span),
+                };
+                self.diagnostics.push(diagnostic);
+                self.synchronize(start, in_block);
+                None
+            }
+        }
+    }
+    
+    // Panic-mode recovery: discards tokens up to where the next statement
+    // likely starts. That is after a `;` or line break outside of the
+    // brackets the statement opened, before a statement keyword, or before
+    // the `}` closing the enclosing block. Parens and square brackets still
+    // open at a `;`, `}` or keyword were never closed, so they stop counting.
+    fn synchronize(&mut self, start: usize, in_block: bool) {
+        let mut open = Vec::new();
+        for token in &self.tokens[start..self.current] {
+            Self::track_bracket(&mut open, &token.kind);
+        }
+        
+        // A statement that broke off right after a block, as in
+        // `function f( { } g()`, resumes with whatever follows the block
+        if self.current > start && self.previous().kind == TokenKind::RightBrace {
+            Self::drop_unclosed(&mut open);
+            if open.is_empty() {
+                return;
+            }
+        }
+        
+        // Always make progress, even if the statement failed on its first token
+        if self.current == start && !(in_block && self.check(&TokenKind::RightBrace)) {
+            self.advance_bracket(&mut open);
+        }
+        
+        // The head of a `for` is the one paren a `;` can be inside
+        let is_for = self.tokens[start..]
+            .iter()
+            .find(|token| token.kind != TokenKind::Newline)
+            .is_some_and(|token| token.kind == TokenKind::For);
+        
+        while !self.is_at_end() {
+            let kind = self.peek().kind.clone();
+            match kind {
+                TokenKind::Semicolon | TokenKind::Newline => {
+                    if kind == TokenKind::Semicolon && !(is_for && open.len() == 1) {
+                        Self::drop_unclosed(&mut open);
+                    }
+                    if open.is_empty() {
+                        self.advance();
+                        return;
+                    }
+                }
+                TokenKind::RightBrace => {
+                    Self::drop_unclosed(&mut open);
+                    if open.is_empty() && in_block {
+                        return;
+                    }
+                }
+                TokenKind::Let | TokenKind::Const | TokenKind::Var | TokenKind::If | TokenKind::While
+                | TokenKind::Do | TokenKind::For | TokenKind::Switch | TokenKind::Try | TokenKind::Throw
+                | TokenKind::Return | TokenKind::Break | TokenKind::Continue => {
+                    Self::drop_unclosed(&mut open);
+                    if open.is_empty() {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.advance_bracket(&mut open);
+        }
+    }
+    
+    fn advance_bracket(&mut self, open: &mut Vec<TokenKind>) {
+        let kind = self.advance().kind.clone();
+        Self::track_bracket(open, &kind);
+    }
+    
+    fn track_bracket(open: &mut Vec<TokenKind>, kind: &TokenKind) {
+        match Self::bracket_delta(kind) {
+            1 => open.push(kind.clone()),
+            -1 => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    
+    // Forgets the parens, square brackets and `${` innermost in `open`
+    fn drop_unclosed(open: &mut Vec<TokenKind>) {
+        while open.last().is_some_and(|kind| *kind != TokenKind::LeftBrace) {
+            open.pop();
+        }
+    }
+    
+    // How a token changes the bracket nesting; `${` and its `}` count too
+    fn bracket_delta(kind: &TokenKind) -> i32 {
+        match kind {
+            TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace | TokenKind::TemplateHead => 1,
+            TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace | TokenKind::TemplateTail => -1,
+            _ => 0,
+        }
     }
     
     fn statement(&mut self) -> Result<Statement> {
+        self.check_nesting()?;
+        self.skip_newlines();
+        
+        let start = self.peek().span;
+        let pending = self.cover_initializers.len();
+        let kind = self.statement_kind()?;
+        
+        // Any `{ key = value }` the statement left in an ordinary object literal
+        if let Some(&span) = self.cover_initializers.get(pending) {
+            self.cover_initializers.truncate(pending);
+            return Err(self.error_at(span, ErrorCode::UnexpectedToken, "Invalid shorthand property initializer"));
+        }
+        Ok(Statement { kind, span: start.to(self.previous().span) })
+    }
+    
+    fn statement_kind(&mut self) -> Result<StatementKind> {
         if self.match_token(&TokenKind::Let) || self.match_token(&TokenKind::Const) || self.match_token(&TokenKind::Var) {
             self.var_declaration()
         } else if self.match_token(&TokenKind::Function) {
@@ -152,6 +777,18 @@ impl Parser {
             self.if_statement()
         } else if self.match_token(&TokenKind::While) {
             self.while_statement()
+        } else if self.match_token(&TokenKind::Do) {
+            self.do_while_statement()
+        } else if self.match_token(&TokenKind::Switch) {
+            self.switch_statement()
+        } else if self.match_token(&TokenKind::For) {
+            self.for_statement()
+        } else if self.match_token(&TokenKind::Break) {
+            self.break_statement()
+        } else if self.match_token(&TokenKind::Continue) {
+            self.continue_statement()
+        } else if self.check(&TokenKind::Identifier) && self.check_next(&TokenKind::Colon) {
+            self.labeled_statement()
         } else if self.match_token(&TokenKind::Return) {
             self.return_statement()
         } else if self.match_token(&TokenKind::Try) {
@@ -159,81 +796,556 @@ impl Parser {
         } else if self.match_token(&TokenKind::Throw) {
             self.throw_statement()
         } else if self.match_token(&TokenKind::LeftBrace) {
-            Ok(Statement::Block(self.block()?))
+            Ok(StatementKind::Block(self.block()?))
         } else {
-            Ok(Statement::Expression(self.expression()?))
+            let expr = self.expression()?;
+            self.consume_semicolon();
+            Ok(StatementKind::Expression(expr))
+        }
+    }
+    
+    fn var_declaration(&mut self) -> Result<StatementKind> {
+        let kind = self.declaration_kind();
+        let target = self.binding_pattern()?;
+        let declaration = self.finish_var_declaration(target, kind)?;
+        
+        self.consume_semicolon();
+        
+        Ok(declaration)
+    }
+    
+    // Maps the let/const/var keyword just consumed to its declaration kind
+    fn declaration_kind(&self) -> DeclarationKind {
+        match self.previous().kind {
+            TokenKind::Var => DeclarationKind::Var,
+            TokenKind::Let => DeclarationKind::Let,
+            _ => DeclarationKind::Const,
+        }
+    }
+    
+    fn finish_var_declaration(&mut self, target: Pattern, kind: DeclarationKind) -> Result<StatementKind> {
+        let value = if self.match_token(&TokenKind::Equal) {
+            Some(self.expression()?)
+        } else {
+            match &target {
+                Pattern::Identifier(name) if kind == DeclarationKind::Const => {
+                    return Err(self.error_at(self.previous().span, ErrorCode::MissingInitializer, &format!("Missing initializer in const declaration '{}'", name)));
+                }
+                Pattern::Identifier(_) => None,
+                _ => {
+                    return Err(self.error_at(self.previous().span, ErrorCode::MissingInitializer, "Missing initializer in destructuring declaration"));
+                }
+            }
+        };
+        
+        Ok(StatementKind::VarDeclaration { target, value, kind })
+    }
+    
+    // A name to bind, or an object or array destructuring pattern
+    fn binding_pattern(&mut self) -> Result<Pattern> {
+        if self.match_token(&TokenKind::LeftBrace) {
+            return self.object_pattern();
+        }
+        if self.match_token(&TokenKind::LeftBracket) {
+            return self.array_pattern();
+        }
+        let name = self.consume(&TokenKind::Identifier, "Expected variable name")?.lexeme.clone();
+        Ok(Pattern::Identifier(name))
+    }
+    
+    // A pattern inside a destructuring pattern, with its optional default
+    fn pattern_element(&mut self) -> Result<PatternElement> {
+        let target = self.binding_pattern()?;
+        let default = if self.match_token(&TokenKind::Equal) {
+            Some(self.assignment()?)
+        } else {
+            None
+        };
+        Ok(PatternElement { target, default })
+    }
+    
+    // `{` was just consumed
+    fn object_pattern(&mut self) -> Result<Pattern> {
+        let mut properties = Vec::new();
+        let mut rest = None;
+        
+        loop {
+            self.skip_newlines();
+            if self.check(&TokenKind::RightBrace) {
+                break;
+            }
+            if self.match_token(&TokenKind::DotDotDot) {
+                rest = Some(self.consume(&TokenKind::Identifier, "Expected name after '...'")?.lexeme.clone());
+                self.skip_newlines();
+                break;
+            }
+            
+            let shorthand = self.check(&TokenKind::Identifier);
+            let key = if self.at_identifier_name() || self.check(&TokenKind::String) {
+                self.advance().lexeme.clone()
+            } else {
+                return Err(self.error_at_current("Expected property name"));
+            };
+            
+            // `{ key }` and `{ key = default }` bind the property to its own name
+            let element = if self.match_token(&TokenKind::Colon) {
+                self.pattern_element()?
+            } else if shorthand {
+                let default = if self.match_token(&TokenKind::Equal) {
+                    Some(self.assignment()?)
+                } else {
+                    None
+                };
+                PatternElement { target: Pattern::Identifier(key.clone()), default }
+            } else {
+                return Err(self.error_at_current("Expected ':' after property name"));
+            };
+            properties.push((key, element));
+            
+            self.skip_newlines();
+            if !self.match_token(&TokenKind::Comma) {
+                break;
+            }
+        }
+        
+        self.consume(&TokenKind::RightBrace, "Expected '}' after destructuring pattern")?;
+        Ok(Pattern::Object { properties, rest })
+    }
+    
+    // `[` was just consumed
+    fn array_pattern(&mut self) -> Result<Pattern> {
+        let mut elements = Vec::new();
+        let mut rest = None;
+        
+        loop {
+            self.skip_newlines();
+            if self.check(&TokenKind::RightBracket) {
+                break;
+            }
+            if self.match_token(&TokenKind::Comma) {
+                elements.push(None);
+                continue;
+            }
+            if self.match_token(&TokenKind::DotDotDot) {
+                rest = Some(Box::new(self.binding_pattern()?));
+                self.skip_newlines();
+                break;
+            }
+            
+            elements.push(Some(self.pattern_element()?));
+            
+            self.skip_newlines();
+            if !self.match_token(&TokenKind::Comma) {
+                break;
+            }
+        }
+        
+        self.consume(&TokenKind::RightBracket, "Expected ']' after destructuring pattern")?;
+        Ok(Pattern::Array { elements, rest })
+    }
+    
+    fn function_declaration(&mut self) -> Result<StatementKind> {
+        let name = self.consume(&TokenKind::Identifier, "Expected function name")?.lexeme.clone();
+        
+        self.consume(&TokenKind::LeftParen, "Expected '(' after function name")?;
+        let params = self.parameters()?;
+        self.consume(&TokenKind::LeftBrace, "Expected '{' before function body")?;
+        let body = self.function_body()?;
+        
+        Ok(StatementKind::FunctionDeclaration {
+            name,
+            params,
+            body,
+            is_async: false, // TODO: Handle async functions
+        })
+    }
+    
+    // `function` was just consumed in an expression; the name is optional
+    fn function_expression(&mut self) -> Result<Expression> {
+        let start = self.previous().span;
+        let name = if self.match_token(&TokenKind::Identifier) {
+            Some(self.previous().lexeme.clone())
+        } else {
+            None
+        };
+        
+        self.consume(&TokenKind::LeftParen, "Expected '(' after function")?;
+        let params = self.parameters()?;
+        self.consume(&TokenKind::LeftBrace, "Expected '{' before function body")?;
+        let body = self.function_body()?;
+        
+        let span = start.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::Function { name, params, body, is_arrow: false }, span))
+    }
+    
+    // Whether an arrow function starts here: `x =>` or `(...) =>`
+    fn at_arrow_function(&self) -> bool {
+        match self.peek().kind {
+            TokenKind::Identifier => self.check_next(&TokenKind::Arrow),
+            TokenKind::LeftParen => match self.closing_brackets.get(&self.current) {
+                Some(&close) => self.tokens.get(close + 1).is_some_and(|next| next.kind == TokenKind::Arrow),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+    
+    fn arrow_function(&mut self) -> Result<Expression> {
+        let start = self.peek().span;
+        let params = if self.match_token(&TokenKind::Identifier) {
+            let name = self.previous().lexeme.clone();
+            vec![Parameter { target: Pattern::Identifier(name), default: None, rest: false }]
+        } else {
+            self.consume(&TokenKind::LeftParen, "Expected '(' before arrow function parameters")?;
+            self.parameters()?
+        };
+        self.consume(&TokenKind::Arrow, "Expected '=>' after arrow function parameters")?;
+        self.skip_newlines();
+        
+        let body = if self.match_token(&TokenKind::LeftBrace) {
+            self.function_body()?
+        } else {
+            let value = self.assignment()?;
+            let span = value.span;
+            vec![Statement { kind: StatementKind::Return(Some(value)), span }]
+        };
+        
+        let span = start.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::Function { name: None, params, body, is_arrow: true }, span))
+    }
+    
+    // The parameters after a '(', up to and including the ')'
+    fn parameters(&mut self) -> Result<Vec<Parameter>> {
+        let mut params = Vec::new();
+        self.skip_newlines();
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if self.match_token(&TokenKind::DotDotDot) {
+                    let target = self.binding_pattern()?;
+                    params.push(Parameter { target, default: None, rest: true });
+                    self.skip_newlines();
+                    if !self.check(&TokenKind::RightParen) {
+                        return Err(self.error_at_current("Expected ')' after rest parameter, which must be last"));
+                    }
+                    break;
+                }
+                
+                let target = self.binding_pattern()?;
+                let default = if self.match_token(&TokenKind::Equal) {
+                    Some(self.assignment()?)
+                } else {
+                    None
+                };
+                params.push(Parameter { target, default, rest: false });
+                self.skip_newlines();
+                if !self.match_token(&TokenKind::Comma) {
+                    break;
+                }
+                self.skip_newlines();
+            }
+        }
+        
+        self.consume(&TokenKind::RightParen, "Expected ')' after parameters")?;
+        Ok(params)
+    }
+    
+    // The statements of a function body whose '{' was just consumed
+    fn function_body(&mut self) -> Result<Vec<Statement>> {
+        // break and continue cannot cross a function boundary
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let switch_depth = std::mem::take(&mut self.switch_depth);
+        let labels = std::mem::take(&mut self.labels);
+        let body = self.block();
+        self.loop_depth = loop_depth;
+        self.switch_depth = switch_depth;
+        self.labels = labels;
+        body
+    }
+    
+    fn if_statement(&mut self) -> Result<StatementKind> {
+        self.consume(&TokenKind::LeftParen, "Expected '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(&TokenKind::RightParen, "Expected ')' after if condition")?;
+        
+        let then_branch = Box::new(self.substatement()?);
+        let else_branch = if self.match_token(&TokenKind::Else) {
+            Some(Box::new(self.substatement()?))
+        } else {
+            None
+        };
+        
+        Ok(StatementKind::If { condition, then_branch, else_branch })
+    }
+    
+    fn while_statement(&mut self) -> Result<StatementKind> {
+        self.consume(&TokenKind::LeftParen, "Expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(&TokenKind::RightParen, "Expected ')' after while condition")?;
+        
+        let body = Box::new(self.loop_body()?);
+        
+        Ok(StatementKind::While { condition, body })
+    }
+    
+    fn do_while_statement(&mut self) -> Result<StatementKind> {
+        let body = Box::new(self.loop_body()?);
+        
+        self.skip_newlines();
+        self.consume(&TokenKind::While, "Expected 'while' after do-while body")?;
+        self.consume(&TokenKind::LeftParen, "Expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(&TokenKind::RightParen, "Expected ')' after while condition")?;
+        self.consume_semicolon();
+        
+        Ok(StatementKind::DoWhile { body, condition })
+    }
+    
+    fn switch_statement(&mut self) -> Result<StatementKind> {
+        self.consume(&TokenKind::LeftParen, "Expected '(' after 'switch'")?;
+        let discriminant = self.expression()?;
+        self.consume(&TokenKind::RightParen, "Expected ')' after switch discriminant")?;
+        self.skip_newlines();
+        self.consume(&TokenKind::LeftBrace, "Expected '{' before switch cases")?;
+        
+        self.switch_depth += 1;
+        let cases = self.switch_cases();
+        self.switch_depth -= 1;
+        
+        Ok(StatementKind::Switch { discriminant, cases: cases? })
+    }
+    
+    fn switch_cases(&mut self) -> Result<Vec<SwitchCase>> {
+        let mut cases = Vec::new();
+        let mut has_default = false;
+        
+        loop {
+            self.skip_newlines();
+            if self.match_token(&TokenKind::RightBrace) {
+                return Ok(cases);
+            }
+            
+            let test = if self.match_token(&TokenKind::Case) {
+                Some(self.expression()?)
+            } else if self.match_token(&TokenKind::Default) {
+                if has_default {
+                    let span = self.previous().span;
+                    return Err(self.error_at(span, ErrorCode::UnexpectedToken, "More than one default clause in switch statement"));
+                }
+                has_default = true;
+                None
+            } else {
+                return Err(self.error_at_current("Expected 'case' or 'default'"));
+            };
+            self.consume(&TokenKind::Colon, "Expected ':' after case")?;
+            
+            // A clause's statements run until the next clause or the closing brace
+            let mut body = Vec::new();
+            loop {
+                self.skip_newlines();
+                if self.check(&TokenKind::Case) || self.check(&TokenKind::Default) || self.check(&TokenKind::RightBrace) || self.is_at_end() {
+                    break;
+                }
+                if let Some(statement) = self.recovering_statement(true) {
+                    body.push(statement);
+                }
+            }
+            cases.push(SwitchCase { test, body });
+        }
+    }
+    
+    fn for_statement(&mut self) -> Result<StatementKind> {
+        self.consume(&TokenKind::LeftParen, "Expected '(' after 'for'")?;
+        
+        // for (let x of ...) / for (x in ...) share their head with a C-style init
+        let init_start = self.peek().span;
+        let declaration = if self.match_tokens(&[TokenKind::Let, TokenKind::Const, TokenKind::Var]) {
+            Some(self.declaration_kind())
+        } else {
+            None
+        };
+        
+        let init = if let Some(kind) = declaration {
+            let target = self.binding_pattern()?;
+            if Self::is_of_or_in(self.peek()) {
+                return self.for_in_of(declaration, target);
+            }
+            let init = self.finish_var_declaration(target, kind)?;
+            Some(Box::new(Statement { kind: init, span: init_start.to(self.previous().span) }))
+        } else if let Some(target) = self.for_in_of_target()? {
+            return self.for_in_of(None, target);
+        } else if self.check(&TokenKind::Semicolon) {
+            None
+        } else {
+            let init = self.expression()?;
+            let span = init.span;
+            Some(Box::new(Statement { kind: StatementKind::Expression(init), span }))
+        };
+        self.consume(&TokenKind::Semicolon, "Expected ';' after for loop initializer")?;
+        
+        let condition = if self.check(&TokenKind::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenKind::Semicolon, "Expected ';' after for loop condition")?;
+        
+        let update = if self.check(&TokenKind::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(&TokenKind::RightParen, "Expected ')' after for clauses")?;
+        
+        let body = Box::new(self.loop_body()?);
+        
+        Ok(StatementKind::For { init, condition, update, body })
+    }
+    
+    // The target of a for...in or for...of head without a declaration: any
+    // assignment target, such as a name, `o.key`, `a[i]` or a destructuring
+    // pattern, followed by `in`/`of`. `None` leaves the position unchanged
+    // for a C-style head.
+    fn for_in_of_target(&mut self) -> Result<Option<Pattern>> {
+        let start = self.current;
+        let pending = self.cover_initializers.len();
+        if let Ok(target) = self.call() {
+            if Self::is_of_or_in(self.peek()) {
+                return match target.kind {
+                    ExpressionKind::Identifier(_)
+                    | ExpressionKind::Member { optional: false, .. }
+                    | ExpressionKind::Index { optional: false, .. }
+                    | ExpressionKind::Array(_)
+                    | ExpressionKind::Object(_) => self.expression_to_pattern(target).map(Some),
+                    _ => Err(self.error_at(target.span, ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side in for loop head")),
+                };
+            }
+        }
+        self.current = start;
+        self.cover_initializers.truncate(pending);
+        Ok(None)
+    }
+    
+    // Parses the rest of a for...in or for...of head, starting at `in`/`of`
+    fn for_in_of(&mut self, declaration: Option<DeclarationKind>, target: Pattern) -> Result<StatementKind> {
+        if self.match_token(&TokenKind::In) {
+            let object = self.expression()?;
+            self.consume(&TokenKind::RightParen, "Expected ')' after for...in head")?;
+            let body = Box::new(self.loop_body()?);
+            return Ok(StatementKind::ForIn { declaration, target, object, body });
+        }
+        
+        self.advance(); // contextual `of`
+        let iterable = self.expression()?;
+        self.consume(&TokenKind::RightParen, "Expected ')' after for...of head")?;
+        let body = Box::new(self.loop_body()?);
+        Ok(StatementKind::ForOf { declaration, target, iterable, body })
+    }
+    
+    fn is_of_or_in(token: &Token) -> bool {
+        token.kind == TokenKind::In || (token.kind == TokenKind::Identifier && token.lexeme == "of")
+    }
+    
+    fn loop_body(&mut self) -> Result<Statement> {
+        self.loop_depth += 1;
+        let body = self.substatement();
+        self.loop_depth -= 1;
+        body
+    }
+    
+    // The body of an `if` or loop. A function declaration there acts as if
+    // wrapped in a block, which is where it gets hoisted to.
+    fn substatement(&mut self) -> Result<Statement> {
+        let statement = self.statement()?;
+        match statement.kind {
+            StatementKind::FunctionDeclaration { .. } => {
+                Ok(Statement { span: statement.span, kind: StatementKind::Block(vec![statement]) })
+            }
+            _ => Ok(statement),
         }
     }
     
-    fn var_declaration(&mut self) -> Result<Statement> {
-        let is_const = self.previous().kind == TokenKind::Const;
-        let name = self.consume(&TokenKind::Identifier, "Expected variable name")?.lexeme.clone();
+    fn break_statement(&mut self) -> Result<StatementKind> {
+        let start = self.previous().span;
+        let label = self.jump_label();
+        let span = start.to(self.previous().span);
         
-        let value = if self.match_token(&TokenKind::Equal) {
-            Some(self.expression()?)
-        } else {
-            None
-        };
+        match &label {
+            Some(name) if !self.labels.iter().any(|(l, _)| l == name) => {
+                return Err(self.error_at(span, ErrorCode::UndefinedLabel, &format!("Undefined label '{}'", name)));
+            }
+            None if self.loop_depth == 0 && self.switch_depth == 0 => {
+                return Err(self.error_at(span, ErrorCode::IllegalJump, "Illegal break statement outside of a loop or switch"));
+            }
+            _ => {}
+        }
         
         self.consume_semicolon();
-        
-        Ok(Statement::VarDeclaration { name, value, is_const })
+        Ok(StatementKind::Break(label))
     }
     
-    fn function_declaration(&mut self) -> Result<Statement> {
-        let name = self.consume(&TokenKind::Identifier, "Expected function name")?.lexeme.clone();
-        
-        self.consume(&TokenKind::LeftParen, "Expected '(' after function name")?;
+    fn continue_statement(&mut self) -> Result<StatementKind> {
+        let start = self.previous().span;
+        let label = self.jump_label();
+        let span = start.to(self.previous().span);
         
-        let mut params = Vec::new();
-        if !self.check(&TokenKind::RightParen) {
-            loop {
-                params.push(self.consume(&TokenKind::Identifier, "Expected parameter name")?.lexeme.clone());
-                if !self.match_token(&TokenKind::Comma) {
-                    break;
+        if self.loop_depth == 0 {
+            return Err(self.error_at(span, ErrorCode::IllegalJump, "Illegal continue statement outside of a loop"));
+        }
+        if let Some(name) = &label {
+            match self.labels.iter().find(|(l, _)| l == name) {
+                Some((_, true)) => {}
+                Some((_, false)) => {
+                    return Err(self.error_at(span, ErrorCode::IllegalJump, &format!("Label '{}' does not refer to a loop", name)));
                 }
+                None => return Err(self.error_at(span, ErrorCode::UndefinedLabel, &format!("Undefined label '{}'", name))),
             }
         }
         
-        self.consume(&TokenKind::RightParen, "Expected ')' after parameters")?;
-        self.consume(&TokenKind::LeftBrace, "Expected '{' before function body")?;
-        
-        let body = self.block()?;
-        
-        Ok(Statement::FunctionDeclaration {
-            name,
-            params,
-            body,
-            is_async: false, // TODO: Handle async functions
-        })
+        self.consume_semicolon();
+        Ok(StatementKind::Continue(label))
     }
     
-    fn if_statement(&mut self) -> Result<Statement> {
-        self.consume(&TokenKind::LeftParen, "Expected '(' after 'if'")?;
-        let condition = self.expression()?;
-        self.consume(&TokenKind::RightParen, "Expected ')' after if condition")?;
-        
-        let then_branch = Box::new(self.statement()?);
-        let else_branch = if self.match_token(&TokenKind::Else) {
-            Some(Box::new(self.statement()?))
+    // The optional label after break/continue, which must be on the same line
+    fn jump_label(&mut self) -> Option<String> {
+        if self.match_token(&TokenKind::Identifier) {
+            Some(self.previous().lexeme.clone())
         } else {
             None
-        };
-        
-        Ok(Statement::If { condition, then_branch, else_branch })
+        }
     }
     
-    fn while_statement(&mut self) -> Result<Statement> {
-        self.consume(&TokenKind::LeftParen, "Expected '(' after 'while'")?;
-        let condition = self.expression()?;
-        self.consume(&TokenKind::RightParen, "Expected ')' after while condition")?;
+    fn labeled_statement(&mut self) -> Result<StatementKind> {
+        let label_token = self.advance();
+        let (label, label_span) = (label_token.lexeme.clone(), label_token.span);
+        self.consume(&TokenKind::Colon, "Expected ':' after label")?;
+        self.skip_newlines();
+        
+        if self.labels.iter().any(|(l, _)| *l == label) {
+            return Err(self.error_at(label_span, ErrorCode::DuplicateLabel, &format!("Label '{}' has already been declared", label)));
+        }
         
-        let body = Box::new(self.statement()?);
+        let is_loop = self.at_loop_statement();
+        self.labels.push((label.clone(), is_loop));
+        let body = self.statement();
+        self.labels.pop();
         
-        Ok(Statement::While { condition, body })
+        Ok(StatementKind::Labeled { label, body: Box::new(body?) })
+    }
+    
+    // Whether the upcoming statement, after any further labels, is a loop
+    fn at_loop_statement(&self) -> bool {
+        let mut i = self.current;
+        loop {
+            match self.tokens[i].kind {
+                TokenKind::While | TokenKind::Do | TokenKind::For => return true,
+                TokenKind::Identifier if self.tokens[i + 1].kind == TokenKind::Colon => i += 2,
+                TokenKind::Newline => i += 1,
+                _ => return false,
+            }
+        }
     }
     
-    fn return_statement(&mut self) -> Result<Statement> {
+    fn return_statement(&mut self) -> Result<StatementKind> {
         let value = if self.check(&TokenKind::Semicolon) || self.check(&TokenKind::Newline) {
             None
         } else {
@@ -241,35 +1353,53 @@ impl Parser {
         };
         
         self.consume_semicolon();
-        Ok(Statement::Return(value))
+        Ok(StatementKind::Return(value))
     }
     
-    fn try_statement(&mut self) -> Result<Statement> {
-        self.consume(&TokenKind::LeftBrace, "Expected '{' after 'try'")?;
-        let try_block = Box::new(Statement::Block(self.block()?));
+    fn try_statement(&mut self) -> Result<StatementKind> {
+        let try_start = self.previous().span;
+        let try_block = Box::new(self.block_statement("Expected '{' after 'try'")?);
         
         let mut catch_param = None;
         let mut catch_block = None;
+        let mut finally_block = None;
         
+        self.skip_newlines_before(&TokenKind::Catch);
         if self.match_token(&TokenKind::Catch) {
             if self.match_token(&TokenKind::LeftParen) {
-                if self.match_token(&TokenKind::Identifier) {
-                    catch_param = Some(self.previous().lexeme.clone());
+                if !self.check(&TokenKind::RightParen) {
+                    catch_param = Some(self.binding_pattern()?);
                 }
                 self.consume(&TokenKind::RightParen, "Expected ')' after catch parameter")?;
             }
             
-            self.consume(&TokenKind::LeftBrace, "Expected '{' after catch")?;
-            catch_block = Some(Box::new(Statement::Block(self.block()?)));
+            catch_block = Some(Box::new(self.block_statement("Expected '{' after catch")?));
+        }
+        
+        self.skip_newlines_before(&TokenKind::Finally);
+        if self.match_token(&TokenKind::Finally) {
+            finally_block = Some(Box::new(self.block_statement("Expected '{' after finally")?));
         }
         
-        Ok(Statement::Try { try_block, catch_param, catch_block })
+        if catch_block.is_none() && finally_block.is_none() {
+            let span = try_start.to(self.previous().span);
+            return Err(self.error_at(span, ErrorCode::ExpectedToken, "Missing catch or finally after try"));
+        }
+        
+        Ok(StatementKind::Try { try_block, catch_param, catch_block, finally_block })
     }
     
-    fn throw_statement(&mut self) -> Result<Statement> {
+    fn throw_statement(&mut self) -> Result<StatementKind> {
         let expr = self.expression()?;
         self.consume_semicolon();
-        Ok(Statement::Throw(expr))
+        Ok(StatementKind::Throw(expr))
+    }
+    
+    // A `{ ... }` block statement, reporting `message` if the brace is missing
+    fn block_statement(&mut self, message: &str) -> Result<Statement> {
+        let start = self.consume(&TokenKind::LeftBrace, message)?.span;
+        let statements = self.block()?;
+        Ok(Statement { kind: StatementKind::Block(statements), span: start.to(self.previous().span) })
     }
     
     fn block(&mut self) -> Result<Vec<Statement>> {
@@ -280,7 +1410,9 @@ impl Parser {
                 self.advance();
                 continue;
             }
-            statements.push(self.statement()?);
+            if let Some(statement) = self.recovering_statement(true) {
+                statements.push(statement);
+            }
         }
         
         self.consume(&TokenKind::RightBrace, "Expected '}' after block")?;
@@ -292,45 +1424,267 @@ impl Parser {
     }
     
     fn assignment(&mut self) -> Result<Expression> {
-        let expr = self.or()?;
+        self.check_nesting()?;
+        if self.at_arrow_function() {
+            return self.arrow_function();
+        }
+        
+        let expr = self.conditional()?;
         
         if self.match_token(&TokenKind::Equal) {
-            if let Expression::Identifier(name) = expr {
-                let value = Box::new(self.assignment()?);
-                return Ok(Expression::Assignment { target: name, value });
+            if matches!(expr.kind, ExpressionKind::Array(_) | ExpressionKind::Object(_)) {
+                let span = expr.span;
+                let target = self.expression_to_pattern(expr)?;
+                let value = self.assignment()?;
+                let span = span.to(value.span);
+                return Ok(Expression::new(
+                    ExpressionKind::DestructuringAssignment { target, value: Box::new(value) },
+                    span,
+                ));
+            }
+            if !Self::is_assignment_target(&expr) {
+                return Err(self.error_at(expr.span, ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side in assignment"));
             }
+            let value = self.assignment()?;
+            let span = expr.span.to(value.span);
+            return Ok(Expression::new(
+                ExpressionKind::Assignment { target: Box::new(expr), value: Box::new(value) },
+                span,
+            ));
+        }
+        
+        if self.match_tokens(&[
+            TokenKind::PlusEqual, TokenKind::MinusEqual, TokenKind::StarEqual,
+            TokenKind::StarStarEqual, TokenKind::SlashEqual, TokenKind::PercentEqual,
+            TokenKind::AndAndEqual, TokenKind::OrOrEqual, TokenKind::QuestionQuestionEqual,
+            TokenKind::AmpEqual, TokenKind::PipeEqual, TokenKind::CaretEqual,
+            TokenKind::LessLessEqual, TokenKind::GreaterGreaterEqual, TokenKind::GreaterGreaterGreaterEqual,
+        ]) {
+            let operator = match self.previous().kind {
+                TokenKind::PlusEqual => BinaryOp::Add,
+                TokenKind::MinusEqual => BinaryOp::Sub,
+                TokenKind::StarEqual => BinaryOp::Mul,
+                TokenKind::StarStarEqual => BinaryOp::Pow,
+                TokenKind::SlashEqual => BinaryOp::Div,
+                TokenKind::PercentEqual => BinaryOp::Mod,
+                TokenKind::AndAndEqual => BinaryOp::And,
+                TokenKind::OrOrEqual => BinaryOp::Or,
+                TokenKind::QuestionQuestionEqual => BinaryOp::Nullish,
+                TokenKind::AmpEqual => BinaryOp::BitAnd,
+                TokenKind::PipeEqual => BinaryOp::BitOr,
+                TokenKind::CaretEqual => BinaryOp::BitXor,
+                TokenKind::LessLessEqual => BinaryOp::ShiftLeft,
+                TokenKind::GreaterGreaterEqual => BinaryOp::ShiftRight,
+                TokenKind::GreaterGreaterGreaterEqual => BinaryOp::UnsignedShiftRight,
+                _ => unreachable!(),
+            };
+            if !Self::is_assignment_target(&expr) {
+                return Err(self.error_at(expr.span, ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side in assignment"));
+            }
+            let value = self.assignment()?;
+            let span = expr.span.to(value.span);
+            return Ok(Expression::new(
+                ExpressionKind::CompoundAssignment { target: Box::new(expr), operator, value: Box::new(value) },
+                span,
+            ));
+        }
+        
+        Ok(expr)
+    }
+    
+    fn conditional(&mut self) -> Result<Expression> {
+        let condition = self.nullish()?;
+        
+        self.skip_newlines_before(&TokenKind::Question);
+        if !self.match_token(&TokenKind::Question) {
+            return Ok(condition);
+        }
+        
+        self.skip_newlines();
+        let then_branch = self.assignment()?;
+        self.skip_newlines_before(&TokenKind::Colon);
+        self.consume(&TokenKind::Colon, "Expected ':' in conditional expression")?;
+        self.skip_newlines();
+        let else_branch = self.assignment()?;
+        
+        let span = condition.span.to(else_branch.span);
+        Ok(Expression::new(
+            ExpressionKind::Conditional {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            },
+            span,
+        ))
+    }
+    
+    // Reinterprets an array or object literal on the left of `=` as the
+    // pattern it spells out
+    fn expression_to_pattern(&mut self, expr: Expression) -> Result<Pattern> {
+        match expr.kind {
+            ExpressionKind::Identifier(name) => Ok(Pattern::Identifier(name)),
+            ExpressionKind::Member { optional: false, .. } | ExpressionKind::Index { optional: false, .. } => {
+                Ok(Pattern::Target(Box::new(expr)))
+            }
+            ExpressionKind::Array(items) => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                let count = items.len();
+                for (index, item) in items.into_iter().enumerate() {
+                    match item.kind {
+                        ExpressionKind::Spread(target) if index + 1 == count => {
+                            rest = Some(Box::new(self.expression_to_pattern(*target)?));
+                        }
+                        ExpressionKind::Spread(_) => {
+                            return Err(self.error_at(item.span, ErrorCode::InvalidAssignmentTarget, "Rest element must be last element"));
+                        }
+                        kind => elements.push(Some(self.expression_to_element(Expression::new(kind, item.span))?)),
+                    }
+                }
+                Ok(Pattern::Array { elements, rest })
+            }
+            ExpressionKind::Object(entries) => {
+                let mut properties = Vec::new();
+                let mut rest = None;
+                let count = entries.len();
+                for (index, entry) in entries.into_iter().enumerate() {
+                    match entry {
+                        ObjectProperty::KeyValue(key, value) => {
+                            // `{ key = value }` is valid here
+                            self.cover_initializers.retain(|span| *span != value.span);
+                            properties.push((key, self.expression_to_element(value)?));
+                        }
+                        ObjectProperty::Spread(Expression { kind: ExpressionKind::Identifier(name), .. }) if index + 1 == count => {
+                            rest = Some(name);
+                        }
+                        ObjectProperty::Spread(target) => {
+                            return Err(self.error_at(target.span, ErrorCode::InvalidAssignmentTarget, "Invalid rest element in destructuring assignment"));
+                        }
+                    }
+                }
+                Ok(Pattern::Object { properties, rest })
+            }
+            _ => Err(self.error_at(expr.span, ErrorCode::InvalidAssignmentTarget, "Invalid destructuring assignment target")),
+        }
+    }
+    
+    // An element of an assignment pattern, where `target = value` gives a default
+    fn expression_to_element(&mut self, expr: Expression) -> Result<PatternElement> {
+        match expr.kind {
+            ExpressionKind::Assignment { target, value } => Ok(PatternElement {
+                target: self.expression_to_pattern(*target)?,
+                default: Some(*value),
+            }),
+            kind => Ok(PatternElement {
+                target: self.expression_to_pattern(Expression::new(kind, expr.span))?,
+                default: None,
+            }),
+        }
+    }
+    
+    fn is_assignment_target(expr: &Expression) -> bool {
+        matches!(expr.kind, ExpressionKind::Identifier(_) | ExpressionKind::Member { .. } | ExpressionKind::Index { .. })
+    }
+    
+    fn binary(left: Expression, operator: BinaryOp, right: Expression) -> Expression {
+        let span = left.span.to(right.span);
+        Expression::new(
+            ExpressionKind::Binary { left: Box::new(left), operator, right: Box::new(right) },
+            span,
+        )
+    }
+    
+    fn nullish(&mut self) -> Result<Expression> {
+        let start = self.current;
+        let start_span = self.peek().span;
+        let mut expr = self.or()?;
+        let mut mixed = false;
+        
+        while self.check(&TokenKind::QuestionQuestion) {
+            mixed |= self.has_bare_logical_operator(start);
+            self.advance();
+            
+            let right_start = self.current;
+            let right = self.or()?;
+            mixed |= self.has_bare_logical_operator(right_start);
+            expr = Self::binary(expr, BinaryOp::Nullish, right);
+        }
+        
+        if mixed {
+            let span = start_span.to(self.previous().span);
+            return Err(self.error_at(span, ErrorCode::MixedNullish, "Cannot mix '??' with '&&' or '||' without parentheses"));
         }
         
         Ok(expr)
     }
     
+    // Whether the tokens from `start` up to the current position contain a
+    // `&&` or `||` outside of any brackets
+    fn has_bare_logical_operator(&self, start: usize) -> bool {
+        let mut depth = 0i32;
+        for token in &self.tokens[start..self.current] {
+            match token.kind {
+                TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::LeftBrace => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace => depth -= 1,
+                TokenKind::AndAnd | TokenKind::OrOr if depth == 0 => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+    
     fn or(&mut self) -> Result<Expression> {
         let mut expr = self.and()?;
         
         while self.match_token(&TokenKind::OrOr) {
             let operator = BinaryOp::Or;
-            let right = Box::new(self.and()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
-            };
+            let right = self.and()?;
+            expr = Self::binary(expr, operator, right);
         }
         
         Ok(expr)
     }
     
     fn and(&mut self) -> Result<Expression> {
-        let mut expr = self.equality()?;
+        let mut expr = self.bitwise_or()?;
         
         while self.match_token(&TokenKind::AndAnd) {
             let operator = BinaryOp::And;
-            let right = Box::new(self.equality()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
-            };
+            let right = self.bitwise_or()?;
+            expr = Self::binary(expr, operator, right);
+        }
+        
+        Ok(expr)
+    }
+    
+    fn bitwise_or(&mut self) -> Result<Expression> {
+        let mut expr = self.bitwise_xor()?;
+        
+        while self.match_token(&TokenKind::Pipe) {
+            let right = self.bitwise_xor()?;
+            expr = Self::binary(expr, BinaryOp::BitOr, right);
+        }
+        
+        Ok(expr)
+    }
+    
+    fn bitwise_xor(&mut self) -> Result<Expression> {
+        let mut expr = self.bitwise_and()?;
+        
+        while self.match_token(&TokenKind::Caret) {
+            let right = self.bitwise_and()?;
+            expr = Self::binary(expr, BinaryOp::BitXor, right);
+        }
+        
+        Ok(expr)
+    }
+    
+    fn bitwise_and(&mut self) -> Result<Expression> {
+        let mut expr = self.equality()?;
+        
+        while self.match_token(&TokenKind::Amp) {
+            let right = self.equality()?;
+            expr = Self::binary(expr, BinaryOp::BitAnd, right);
         }
         
         Ok(expr)
@@ -339,40 +1693,55 @@ impl Parser {
     fn equality(&mut self) -> Result<Expression> {
         let mut expr = self.comparison()?;
         
-        while self.match_tokens(&[TokenKind::BangEqual, TokenKind::EqualEqual]) {
+        while self.match_tokens(&[
+            TokenKind::BangEqual, TokenKind::EqualEqual,
+            TokenKind::BangEqualEqual, TokenKind::EqualEqualEqual,
+        ]) {
             let operator = match self.previous().kind {
                 TokenKind::BangEqual => BinaryOp::NotEqual,
                 TokenKind::EqualEqual => BinaryOp::Equal,
+                TokenKind::BangEqualEqual => BinaryOp::StrictNotEqual,
+                TokenKind::EqualEqualEqual => BinaryOp::StrictEqual,
                 _ => unreachable!(),
             };
-            let right = Box::new(self.comparison()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
-            };
+            let right = self.comparison()?;
+            expr = Self::binary(expr, operator, right);
         }
         
         Ok(expr)
     }
     
     fn comparison(&mut self) -> Result<Expression> {
-        let mut expr = self.term()?;
+        let mut expr = self.shift()?;
         
-        while self.match_tokens(&[TokenKind::Greater, TokenKind::GreaterEqual, TokenKind::Less, TokenKind::LessEqual]) {
+        while self.match_tokens(&[TokenKind::Greater, TokenKind::GreaterEqual, TokenKind::Less, TokenKind::LessEqual, TokenKind::Instanceof]) {
             let operator = match self.previous().kind {
                 TokenKind::Greater => BinaryOp::Greater,
                 TokenKind::GreaterEqual => BinaryOp::GreaterEqual,
                 TokenKind::Less => BinaryOp::Less,
                 TokenKind::LessEqual => BinaryOp::LessEqual,
+                TokenKind::Instanceof => BinaryOp::Instanceof,
                 _ => unreachable!(),
             };
-            let right = Box::new(self.term()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
+            let right = self.shift()?;
+            expr = Self::binary(expr, operator, right);
+        }
+        
+        Ok(expr)
+    }
+    
+    fn shift(&mut self) -> Result<Expression> {
+        let mut expr = self.term()?;
+        
+        while self.match_tokens(&[TokenKind::LessLess, TokenKind::GreaterGreater, TokenKind::GreaterGreaterGreater]) {
+            let operator = match self.previous().kind {
+                TokenKind::LessLess => BinaryOp::ShiftLeft,
+                TokenKind::GreaterGreater => BinaryOp::ShiftRight,
+                TokenKind::GreaterGreaterGreater => BinaryOp::UnsignedShiftRight,
+                _ => unreachable!(),
             };
+            let right = self.term()?;
+            expr = Self::binary(expr, operator, right);
         }
         
         Ok(expr)
@@ -387,19 +1756,15 @@ impl Parser {
                 TokenKind::Plus => BinaryOp::Add,
                 _ => unreachable!(),
             };
-            let right = Box::new(self.factor()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
-            };
+            let right = self.factor()?;
+            expr = Self::binary(expr, operator, right);
         }
         
         Ok(expr)
     }
     
     fn factor(&mut self) -> Result<Expression> {
-        let mut expr = self.unary()?;
+        let mut expr = self.exponent()?;
         
         while self.match_tokens(&[TokenKind::Slash, TokenKind::Star, TokenKind::Percent]) {
             let operator = match self.previous().kind {
@@ -408,59 +1773,200 @@ impl Parser {
                 TokenKind::Percent => BinaryOp::Mod,
                 _ => unreachable!(),
             };
-            let right = Box::new(self.unary()?);
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator,
-                right,
-            };
+            let right = self.exponent()?;
+            expr = Self::binary(expr, operator, right);
         }
         
         Ok(expr)
     }
     
+    // `**` is right-associative, and its base cannot be a bare unary
+    // expression since `-2 ** 2` would be ambiguous
+    fn exponent(&mut self) -> Result<Expression> {
+        let starts_with_unary = matches!(
+            self.peek().kind,
+            TokenKind::Bang | TokenKind::Minus | TokenKind::Plus | TokenKind::Tilde | TokenKind::Typeof
+                | TokenKind::Void | TokenKind::Delete | TokenKind::Await
+        );
+        let base = self.unary()?;
+        
+        if !self.check(&TokenKind::StarStar) {
+            return Ok(base);
+        }
+        if starts_with_unary {
+            return Err(self.error_at(
+                base.span,
+                ErrorCode::UnexpectedToken,
+                "Unary operator used immediately before exponentiation expression; parentheses are required",
+            ));
+        }
+        
+        self.advance();
+        let exponent = self.exponent()?;
+        Ok(Self::binary(base, BinaryOp::Pow, exponent))
+    }
+    
     fn unary(&mut self) -> Result<Expression> {
+        self.check_nesting()?;
+        let start = self.peek().span;
+        
         if self.match_token(&TokenKind::Await) {
             let expr = self.unary()?;
-            return Ok(Expression::Await(Box::new(expr)));
+            let span = start.to(expr.span);
+            return Ok(Expression::new(ExpressionKind::Await(Box::new(expr)), span));
+        }
+        
+        if self.match_tokens(&[
+            TokenKind::Bang, TokenKind::Minus, TokenKind::Plus, TokenKind::Tilde,
+            TokenKind::Typeof, TokenKind::Void, TokenKind::Delete,
+        ]) {
+            let operator = match self.previous().kind {
+                TokenKind::Bang => UnaryOp::Not,
+                TokenKind::Minus => UnaryOp::Negate,
+                TokenKind::Plus => UnaryOp::Plus,
+                TokenKind::Tilde => UnaryOp::BitNot,
+                TokenKind::Typeof => UnaryOp::Typeof,
+                TokenKind::Void => UnaryOp::Void,
+                TokenKind::Delete => UnaryOp::Delete,
+                _ => unreachable!(),
+            };
+            let operand = self.unary()?;
+            let span = start.to(operand.span);
+            
+            // Fold negative number literals so `-1` stays a plain literal
+            if let (UnaryOp::Negate, ExpressionKind::Literal(ZanoValue::Number(n))) = (operator, &operand.kind) {
+                return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::Number(-n)), span));
+            }
+            
+            return Ok(Expression::new(ExpressionKind::Unary { operator, operand: Box::new(operand) }, span));
+        }
+        
+        if self.match_tokens(&[TokenKind::PlusPlus, TokenKind::MinusMinus]) {
+            let operator = Self::update_operator(&self.previous().kind);
+            let target = self.unary()?;
+            if !Self::is_assignment_target(&target) {
+                return Err(self.error_at(target.span, ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side expression in prefix operation"));
+            }
+            let span = start.to(target.span);
+            return Ok(Expression::new(ExpressionKind::Update { operator, prefix: true, target: Box::new(target) }, span));
+        }
+        
+        self.postfix()
+    }
+    
+    fn postfix(&mut self) -> Result<Expression> {
+        let expr = self.call()?;
+        
+        // A newline before `++` or `--` ends the statement instead, as in JavaScript
+        if self.match_tokens(&[TokenKind::PlusPlus, TokenKind::MinusMinus]) {
+            let operator = Self::update_operator(&self.previous().kind);
+            if !Self::is_assignment_target(&expr) {
+                return Err(self.error_at(expr.span, ErrorCode::InvalidAssignmentTarget, "Invalid left-hand side expression in postfix operation"));
+            }
+            let span = expr.span.to(self.previous().span);
+            return Ok(Expression::new(ExpressionKind::Update { operator, prefix: false, target: Box::new(expr) }, span));
         }
         
-        self.call()
+        Ok(expr)
+    }
+    
+    fn update_operator(kind: &TokenKind) -> UpdateOp {
+        match kind {
+            TokenKind::PlusPlus => UpdateOp::Increment,
+            _ => UpdateOp::Decrement,
+        }
     }
     
     fn call(&mut self) -> Result<Expression> {
-        let mut expr = self.primary()?;
+        let mut expr = if self.match_token(&TokenKind::New) {
+            self.new_expression()?
+        } else {
+            self.primary()?
+        };
+        let mut in_optional_chain = false;
         
         loop {
-            if self.match_token(&TokenKind::LeftParen) {
-                expr = self.finish_call(expr)?;
-            } else if self.match_token(&TokenKind::Dot) {
-                let name = self.consume(&TokenKind::Identifier, "Expected property name after '.'")?;
-                expr = Expression::Member {
-                    object: Box::new(expr),
-                    property: name.lexeme.clone(),
+            if self.match_token(&TokenKind::QuestionDot) {
+                in_optional_chain = true;
+                expr = if self.match_token(&TokenKind::LeftParen) {
+                    self.finish_call(expr, true)?
+                } else if self.match_token(&TokenKind::LeftBracket) {
+                    self.finish_index(expr, true)?
+                } else {
+                    self.finish_member(expr, true)?
                 };
+            } else if self.match_token(&TokenKind::LeftParen) {
+                expr = self.finish_call(expr, false)?;
+            } else if self.match_token(&TokenKind::Dot) {
+                expr = self.finish_member(expr, false)?;
             } else if self.match_token(&TokenKind::LeftBracket) {
-                let index = self.expression()?;
-                self.consume(&TokenKind::RightBracket, "Expected ']' after array index")?;
-                expr = Expression::Index {
-                    object: Box::new(expr),
-                    index: Box::new(index),
-                };
+                expr = self.finish_index(expr, false)?;
+            } else if self.match_tokens(&[TokenKind::TemplateString, TokenKind::TemplateHead]) {
+                if in_optional_chain {
+                    let span = self.previous().span;
+                    return Err(self.error_at(span, ErrorCode::UnexpectedToken, "Invalid tagged template on optional chain"));
+                }
+                let (quasis, expressions) = self.template()?;
+                let span = expr.span.to(self.previous().span);
+                let cooked = quasis.iter().map(|quasi| quasi.cooked.clone()).collect();
+                let raw = quasis.into_iter().map(|quasi| quasi.lexeme).collect();
+                expr = Expression::new(
+                    ExpressionKind::TaggedTemplate { tag: Box::new(expr), cooked, raw, expressions },
+                    span,
+                );
             } else {
                 break;
             }
         }
         
+        if in_optional_chain {
+            let span = expr.span;
+            expr = Expression::new(ExpressionKind::OptionalChain(Box::new(expr)), span);
+        }
         Ok(expr)
     }
     
-    fn finish_call(&mut self, callee: Expression) -> Result<Expression> {
+    // Whether the next token is an IdentifierName: an identifier or any
+    // reserved word, all of which can name a property after `.` or as a key
+    fn at_identifier_name(&self) -> bool {
+        use TokenKind::*;
+        matches!(
+            self.peek().kind,
+            Identifier | Let | Const | Var | Function | If | Else | While | Do | For | In | Break | Continue
+                | Return | Async | Await | Switch | Case | Default | Try | Catch | Finally | Throw | Typeof
+                | Void | Delete | New | This | Instanceof | Boolean | Null | Undefined
+        )
+    }
+    
+    fn finish_member(&mut self, object: Expression, optional: bool) -> Result<Expression> {
+        if !self.at_identifier_name() {
+            return Err(self.error_at_current("Expected property name after '.'"));
+        }
+        let property = self.advance().lexeme.clone();
+        let span = object.span.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::Member { object: Box::new(object), property, optional }, span))
+    }
+    
+    fn finish_index(&mut self, object: Expression, optional: bool) -> Result<Expression> {
+        let index = self.expression()?;
+        self.consume(&TokenKind::RightBracket, "Expected ']' after array index")?;
+        let span = object.span.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::Index { object: Box::new(object), index: Box::new(index), optional }, span))
+    }
+    
+    fn finish_call(&mut self, callee: Expression, optional: bool) -> Result<Expression> {
+        let args = self.arguments()?;
+        let span = callee.span.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::Call { callee: Box::new(callee), args, optional }, span))
+    }
+    
+    // The arguments of a call whose '(' was just consumed
+    fn arguments(&mut self) -> Result<Vec<Expression>> {
         let mut args = Vec::new();
         
         if !self.check(&TokenKind::RightParen) {
             loop {
-                args.push(self.expression()?);
+                args.push(self.element()?);
                 if !self.match_token(&TokenKind::Comma) {
                     break;
                 }
@@ -468,39 +1974,95 @@ impl Parser {
         }
         
         self.consume(&TokenKind::RightParen, "Expected ')' after arguments")?;
+        Ok(args)
+    }
+    
+    // `new` was just consumed. The callee runs up to the first call, so
+    // `new a.B()` constructs `a.B` and `new f()()` calls the result.
+    fn new_expression(&mut self) -> Result<Expression> {
+        let start = self.previous().span;
+        let mut callee = if self.match_token(&TokenKind::New) {
+            self.new_expression()?
+        } else {
+            self.primary()?
+        };
         
-        Ok(Expression::Call {
-            callee: Box::new(callee),
-            args,
-        })
+        loop {
+            if self.match_token(&TokenKind::Dot) {
+                callee = self.finish_member(callee, false)?;
+            } else if self.match_token(&TokenKind::LeftBracket) {
+                callee = self.finish_index(callee, false)?;
+            } else {
+                break;
+            }
+        }
+        
+        let args = if self.match_token(&TokenKind::LeftParen) {
+            self.arguments()?
+        } else {
+            Vec::new()
+        };
+        let span = start.to(self.previous().span);
+        Ok(Expression::new(ExpressionKind::New { callee: Box::new(callee), args }, span))
     }
     
     fn primary(&mut self) -> Result<Expression> {
+        let span = self.peek().span;
+        
         if self.match_token(&TokenKind::Boolean) {
             let value = self.previous().lexeme == "true";
-            return Ok(Expression::Literal(ZanoValue::Boolean(value)));
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::Boolean(value)), span));
         }
         
         if self.match_token(&TokenKind::Null) {
-            return Ok(Expression::Literal(ZanoValue::Null));
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::Null), span));
         }
         
         if self.match_token(&TokenKind::Undefined) {
-            return Ok(Expression::Literal(ZanoValue::Undefined));
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::Undefined), span));
         }
         
         if self.match_token(&TokenKind::Number) {
             let value = self.previous().lexeme.parse::<f64>()?;
-            return Ok(Expression::Literal(ZanoValue::Number(value)));
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::Number(value)), span));
+        }
+        
+        if self.match_token(&TokenKind::BigInt) {
+            let value = self.previous().lexeme.parse::<BigInt>()?;
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::BigInt(value)), span));
         }
         
         if self.match_token(&TokenKind::String) {
             let value = self.previous().lexeme.clone();
-            return Ok(Expression::Literal(ZanoValue::String(value)));
+            return Ok(Expression::new(ExpressionKind::Literal(ZanoValue::String(value)), span));
         }
         
         if self.match_token(&TokenKind::Identifier) {
-            return Ok(Expression::Identifier(self.previous().lexeme.clone()));
+            let name = self.previous().lexeme.clone();
+            return Ok(Expression::new(ExpressionKind::Identifier(name), span));
+        }
+        
+        if self.match_token(&TokenKind::This) {
+            return Ok(Expression::new(ExpressionKind::This, span));
+        }
+        
+        if self.match_token(&TokenKind::Function) {
+            return self.function_expression();
+        }
+        
+        if self.match_tokens(&[TokenKind::TemplateString, TokenKind::TemplateHead]) {
+            let (quasis, expressions) = self.template()?;
+            // Only tagged templates may contain escapes that do not decode
+            let quasis = quasis
+                .into_iter()
+                .map(|quasi| {
+                    quasi.cooked.ok_or_else(|| {
+                        self.error_at(quasi.span, ErrorCode::InvalidEscape, "Invalid escape sequence in template")
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let span = span.to(self.previous().span);
+            return Ok(Expression::new(ExpressionKind::Template { quasis, expressions }, span));
         }
         
         if self.match_token(&TokenKind::LeftParen) {
@@ -517,15 +2079,45 @@ impl Parser {
             return self.object_literal();
         }
         
-        Err(anyhow::anyhow!("Unexpected token: {:?}", self.peek()))
+        Err(self.unexpected_token())
+    }
+    
+    // The text tokens and substitutions of a template whose first token was
+    // just consumed
+    fn template(&mut self) -> Result<(Vec<Token>, Vec<Expression>)> {
+        let mut quasis = vec![self.previous().clone()];
+        let mut expressions = Vec::new();
+        
+        while matches!(self.previous().kind, TokenKind::TemplateHead | TokenKind::TemplateMiddle) {
+            self.skip_newlines();
+            expressions.push(self.expression()?);
+            self.skip_newlines();
+            if !self.match_tokens(&[TokenKind::TemplateMiddle, TokenKind::TemplateTail]) {
+                return Err(self.error_at_current("Expected '}' after template expression"));
+            }
+            quasis.push(self.previous().clone());
+        }
+        
+        Ok((quasis, expressions))
+    }
+    
+    fn unexpected_token(&self) -> anyhow::Error {
+        let token = self.peek();
+        let message = match token.kind {
+            TokenKind::Eof => "Unexpected end of input".to_string(),
+            TokenKind::Newline => "Unexpected line break".to_string(),
+            _ => format!("Unexpected token '{}'", token.lexeme),
+        };
+        self.error_at(token.span, ErrorCode::UnexpectedToken, &message)
     }
     
     fn array_literal(&mut self) -> Result<Expression> {
+        let start = self.previous().span;
         let mut elements = Vec::new();
         
         if !self.check(&TokenKind::RightBracket) {
             loop {
-                elements.push(self.expression()?);
+                elements.push(self.element()?);
                 if !self.match_token(&TokenKind::Comma) {
                     break;
                 }
@@ -533,60 +2125,89 @@ impl Parser {
         }
         
         self.consume(&TokenKind::RightBracket, "Expected ']' after array elements")?;
-        Ok(Expression::Array(elements))
+        Ok(Expression::new(ExpressionKind::Array(elements), start.to(self.previous().span)))
+    }
+    
+    // An argument or array element, which may be spread
+    fn element(&mut self) -> Result<Expression> {
+        if self.match_token(&TokenKind::DotDotDot) {
+            let start = self.previous().span;
+            let value = self.assignment()?;
+            let span = start.to(value.span);
+            return Ok(Expression::new(ExpressionKind::Spread(Box::new(value)), span));
+        }
+        self.expression()
     }
     
     fn object_literal(&mut self) -> Result<Expression> {
+        let start = self.previous().span;
         let mut pairs = Vec::new();
         
         // Skip newlines at the beginning
-        while self.check(&TokenKind::Newline) {
-            self.advance();
-        }
+        self.skip_newlines();
         
         if !self.check(&TokenKind::RightBrace) {
             loop {
                 // Skip newlines before property name
-                while self.check(&TokenKind::Newline) {
-                    self.advance();
+                self.skip_newlines();
+                
+                // A trailing comma
+                if self.check(&TokenKind::RightBrace) {
+                    break;
                 }
                 
-                let key = if self.check(&TokenKind::String) {
-                    self.advance().lexeme.clone()
-                } else if self.check(&TokenKind::Identifier) {
+                if self.match_token(&TokenKind::DotDotDot) {
+                    pairs.push(ObjectProperty::Spread(self.assignment()?));
+                    self.skip_newlines();
+                    if !self.match_token(&TokenKind::Comma) {
+                        break;
+                    }
+                    continue;
+                }
+                
+                let shorthand = self.check(&TokenKind::Identifier);
+                let key_span = self.peek().span;
+                let key = if self.at_identifier_name() || self.check(&TokenKind::String) {
                     self.advance().lexeme.clone()
                 } else {
-                    return Err(anyhow::anyhow!("Expected property name"));
+                    return Err(self.error_at_current("Expected property name"));
                 };
                 
-                self.consume(&TokenKind::Colon, "Expected ':' after property name")?;
-                let value = self.expression()?;
+                let value = if shorthand && !self.check(&TokenKind::Colon) {
+                    // `{ key }` stands for `{ key: key }`
+                    let name = Expression::new(ExpressionKind::Identifier(key.clone()), key_span);
+                    if self.match_token(&TokenKind::Equal) {
+                        let default = self.assignment()?;
+                        let span = key_span.to(default.span);
+                        self.cover_initializers.push(span);
+                        Expression::new(ExpressionKind::Assignment { target: Box::new(name), value: Box::new(default) }, span)
+                    } else {
+                        name
+                    }
+                } else {
+                    self.consume(&TokenKind::Colon, "Expected ':' after property name")?;
+                    self.expression()?
+                };
                 
-                pairs.push((key, value));
+                pairs.push(ObjectProperty::KeyValue(key, value));
                 
                 // Skip newlines before comma or closing brace
-                while self.check(&TokenKind::Newline) {
-                    self.advance();
-                }
+                self.skip_newlines();
                 
                 if !self.match_token(&TokenKind::Comma) {
                     break;
                 }
                 
                 // Skip newlines after comma
-                while self.check(&TokenKind::Newline) {
-                    self.advance();
-                }
+                self.skip_newlines();
             }
         }
         
         // Skip newlines before closing brace
-        while self.check(&TokenKind::Newline) {
-            self.advance();
-        }
+        self.skip_newlines();
         
         self.consume(&TokenKind::RightBrace, "Expected '}' after object properties")?;
-        Ok(Expression::Object(pairs))
+        Ok(Expression::new(ExpressionKind::Object(pairs), start.to(self.previous().span)))
     }
     
     // Helper methods
@@ -617,6 +2238,13 @@ impl Parser {
         }
     }
     
+    fn check_next(&self, kind: &TokenKind) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => &token.kind == kind,
+            None => false,
+        }
+    }
+    
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -640,7 +2268,42 @@ impl Parser {
         if self.check(kind) {
             Ok(self.advance())
         } else {
-            Err(anyhow::anyhow!("{}", message))
+            Err(self.error_at_current(message))
+        }
+    }
+    
+    // How much native stack must be left to parse another level of nesting
+    const STACK_RED_ZONE: usize = 1024 * 1024;
+    
+    // Each level of nested statements or expressions recurses, so deep
+    // nesting is an error rather than a native stack overflow
+    fn check_nesting(&self) -> Result<()> {
+        if stacker::remaining_stack().is_some_and(|left| left < Self::STACK_RED_ZONE) {
+            return Err(self.error_at(self.peek().span, ErrorCode::TooDeeplyNested, "Code is nested too deeply"));
+        }
+        Ok(())
+    }
+    
+    fn error_at_current(&self, message: &str) -> anyhow::Error {
+        self.error_at(self.peek().span, ErrorCode::ExpectedToken, message)
+    }
+    
+    fn error_at(&self, span: Span, code: ErrorCode, message: &str) -> anyhow::Error {
+        anyhow::Error::new(Diagnostic::new(code, message, span))
+    }
+    
+    fn skip_newlines(&mut self) {
+        while self.check(&TokenKind::Newline) {
+            self.advance();
+        }
+    }
+    
+    // Skips line breaks when the next token after them is `kind`, which lets
+    // an expression continue on the next line, e.g. with `? a` and `: b`
+    fn skip_newlines_before(&mut self, kind: &TokenKind) {
+        let next = self.tokens[self.current..].iter().find(|token| token.kind != TokenKind::Newline);
+        if next.is_some_and(|token| &token.kind == kind) {
+            self.skip_newlines();
         }
     }
     
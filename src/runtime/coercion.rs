@@ -0,0 +1,285 @@
+use crate::parser::ZanoValue;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+use std::cmp::Ordering;
+
+// JavaScript's abstract operations for converting and comparing values
+
+pub fn type_of(value: &ZanoValue) -> &'static str {
+    match value {
+        ZanoValue::Undefined => "undefined",
+        ZanoValue::Null | ZanoValue::Object(_) | ZanoValue::Array(_) => "object",
+        ZanoValue::Boolean(_) => "boolean",
+        ZanoValue::Number(_) => "number",
+        ZanoValue::BigInt(_) => "bigint",
+        ZanoValue::String(_) => "string",
+        ZanoValue::Function(_) => "function",
+    }
+}
+
+/// ToPrimitive: objects, arrays and functions become their string form.
+pub fn to_primitive(value: &ZanoValue) -> ZanoValue {
+    match value {
+        ZanoValue::Object(_) | ZanoValue::Array(_) | ZanoValue::Function(_) => {
+            ZanoValue::String(to_js_string(value))
+        }
+        primitive => primitive.clone(),
+    }
+}
+
+pub fn to_number(value: &ZanoValue) -> f64 {
+    match value {
+        ZanoValue::Undefined => f64::NAN,
+        ZanoValue::Null => 0.0,
+        ZanoValue::Boolean(b) => if *b { 1.0 } else { 0.0 },
+        ZanoValue::Number(n) => *n,
+        // JavaScript refuses to convert implicitly; callers that must reject
+        // BigInts check for them before converting
+        ZanoValue::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+        ZanoValue::String(s) => string_to_number(s),
+        ZanoValue::Object(_) | ZanoValue::Array(_) | ZanoValue::Function(_) => {
+            to_number(&to_primitive(value))
+        }
+    }
+}
+
+/// ToNumeric: the primitive form of a value as a Number, unless it is a BigInt.
+pub fn to_numeric(value: &ZanoValue) -> ZanoValue {
+    match to_primitive(value) {
+        ZanoValue::BigInt(n) => ZanoValue::BigInt(n),
+        primitive => ZanoValue::Number(to_number(&primitive)),
+    }
+}
+
+fn string_to_number(s: &str) -> f64 {
+    let trimmed = s.trim();
+
+    let radix = match trimmed.get(..2) {
+        Some("0x") | Some("0X") => Some(16),
+        Some("0o") | Some("0O") => Some(8),
+        Some("0b") | Some("0B") => Some(2),
+        _ => None,
+    };
+    if let Some(radix) = radix {
+        return u64::from_str_radix(&trimmed[2..], radix)
+            .map(|n| n as f64)
+            .unwrap_or(f64::NAN);
+    }
+
+    match trimmed {
+        "" => 0.0,
+        "Infinity" | "+Infinity" => f64::INFINITY,
+        "-Infinity" => f64::NEG_INFINITY,
+        // Rust also accepts "inf" and "nan", which JavaScript does not
+        _ if trimmed.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) => {
+            trimmed.parse::<f64>().unwrap_or(f64::NAN)
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// StringToBigInt, as used when comparing a BigInt with a string. Only
+/// integers are accepted, so "1.5" and "1e3" give `None`.
+fn string_to_bigint(s: &str) -> Option<BigInt> {
+    let trimmed = s.trim();
+    // The BigInt parser allows digit separators, which strings may not have
+    if trimmed.contains('_') {
+        return None;
+    }
+    let radix = match trimmed.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0o") | Some("0O") => 8,
+        Some("0b") | Some("0B") => 2,
+        _ if trimmed.is_empty() => return Some(BigInt::default()),
+        _ => return trimmed.parse().ok(),
+    };
+    BigInt::parse_bytes(&trimmed.as_bytes()[2..], radix)
+}
+
+/// ToString, e.g. for string concatenation and property keys.
+pub fn to_js_string(value: &ZanoValue) -> String {
+    stringify(value, &mut Vec::new())
+}
+
+// `seen` holds the arrays and objects being converted further up, so that
+// a cycle back to one becomes "" as Array.prototype.join makes it
+fn stringify(value: &ZanoValue, seen: &mut Vec<*const ()>) -> String {
+    match value {
+        ZanoValue::Undefined => "undefined".to_string(),
+        ZanoValue::Null => "null".to_string(),
+        ZanoValue::Boolean(b) => b.to_string(),
+        ZanoValue::Number(n) => number_to_string(*n),
+        ZanoValue::BigInt(n) => n.to_string(),
+        ZanoValue::String(s) => s.clone(),
+        ZanoValue::Array(array) if seen.contains(&array.as_ptr()) => String::new(),
+        ZanoValue::Array(array) => {
+            seen.push(array.as_ptr());
+            let joined = array
+                .read()
+                .values()
+                .iter()
+                .map(|item| match item {
+                    ZanoValue::Null | ZanoValue::Undefined => String::new(),
+                    other => stringify(other, seen),
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            seen.pop();
+            joined
+        }
+        ZanoValue::Object(object) if object.read().error_type.is_some() => {
+            if seen.contains(&object.as_ptr()) {
+                return String::new();
+            }
+            seen.push(object.as_ptr());
+            let string = error_to_string(&object.read().properties, seen);
+            seen.pop();
+            string
+        }
+        ZanoValue::Object(_) => "[object Object]".to_string(),
+        ZanoValue::Function(func) => format!("function {}() {{ [native code] }}", func.name()),
+    }
+}
+
+// Error.prototype.toString: "name: message", leaving out whichever is empty
+fn error_to_string(properties: &IndexMap<String, ZanoValue>, seen: &mut Vec<*const ()>) -> String {
+    let name = match properties.get("name") {
+        None | Some(ZanoValue::Undefined) => "Error".to_string(),
+        Some(name) => stringify(name, seen),
+    };
+    let message = match properties.get("message") {
+        None | Some(ZanoValue::Undefined) => String::new(),
+        Some(message) => stringify(message, seen),
+    };
+    match (name.is_empty(), message.is_empty()) {
+        (_, true) => name,
+        (true, false) => message,
+        (false, false) => format!("{}: {}", name, message),
+    }
+}
+
+pub fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        // Covers -0 as well
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-6 {
+        // JavaScript writes an explicit sign on positive exponents
+        let formatted = format!("{:e}", n);
+        match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+                format!("{}e+{}", mantissa, exponent)
+            }
+            _ => formatted,
+        }
+    } else {
+        n.to_string()
+    }
+}
+
+/// `**`, which differs from `powf` when the exponent is NaN or the base is
+/// ±1 raised to an infinite power: JavaScript gives NaN for both.
+pub fn exponentiate(base: f64, exponent: f64) -> f64 {
+    if exponent.is_nan() || (base.abs() == 1.0 && exponent.is_infinite()) {
+        f64::NAN
+    } else {
+        base.powf(exponent)
+    }
+}
+
+/// ToInt32: wraps the integer part of a number into the signed 32-bit range,
+/// as the bitwise operators do. NaN and the infinities become 0.
+pub fn to_int32(n: f64) -> i32 {
+    to_uint32(n) as i32
+}
+
+/// ToUint32: as `to_int32`, but into the unsigned range.
+pub fn to_uint32(n: f64) -> u32 {
+    if n.is_finite() {
+        n.trunc().rem_euclid(4294967296.0) as u32
+    } else {
+        0
+    }
+}
+
+pub fn to_property_key(value: &ZanoValue) -> String {
+    to_js_string(value)
+}
+
+/// The `===` algorithm: no conversions, and objects compare by identity.
+pub fn strict_equals(a: &ZanoValue, b: &ZanoValue) -> bool {
+    match (a, b) {
+        (ZanoValue::Undefined, ZanoValue::Undefined) | (ZanoValue::Null, ZanoValue::Null) => true,
+        (ZanoValue::Boolean(x), ZanoValue::Boolean(y)) => x == y,
+        (ZanoValue::Number(x), ZanoValue::Number(y)) => x == y,
+        (ZanoValue::BigInt(x), ZanoValue::BigInt(y)) => x == y,
+        (ZanoValue::String(x), ZanoValue::String(y)) => x == y,
+        (ZanoValue::Object(x), ZanoValue::Object(y)) => x.ptr_eq(y),
+        (ZanoValue::Array(x), ZanoValue::Array(y)) => x.ptr_eq(y),
+        (ZanoValue::Function(x), ZanoValue::Function(y)) => std::sync::Arc::ptr_eq(x, y),
+        _ => false,
+    }
+}
+
+/// The `==` algorithm, which converts mismatched operands before comparing.
+pub fn loose_equals(a: &ZanoValue, b: &ZanoValue) -> bool {
+    if std::mem::discriminant(a) == std::mem::discriminant(b) {
+        return strict_equals(a, b);
+    }
+
+    match (a, b) {
+        (ZanoValue::Null | ZanoValue::Undefined, ZanoValue::Null | ZanoValue::Undefined) => true,
+        (ZanoValue::Null | ZanoValue::Undefined, _) | (_, ZanoValue::Null | ZanoValue::Undefined) => false,
+        (ZanoValue::Number(x), ZanoValue::String(_)) => *x == to_number(b),
+        (ZanoValue::String(_), ZanoValue::Number(y)) => to_number(a) == *y,
+        (ZanoValue::BigInt(x), ZanoValue::String(y)) | (ZanoValue::String(y), ZanoValue::BigInt(x)) => {
+            string_to_bigint(y).as_ref() == Some(x)
+        }
+        (ZanoValue::BigInt(x), ZanoValue::Number(y)) | (ZanoValue::Number(y), ZanoValue::BigInt(x)) => {
+            compare_bigint_to_number(x, *y) == Some(Ordering::Equal)
+        }
+        (ZanoValue::Boolean(_), _) => loose_equals(&ZanoValue::Number(to_number(a)), b),
+        (_, ZanoValue::Boolean(_)) => loose_equals(a, &ZanoValue::Number(to_number(b))),
+        (
+            ZanoValue::Object(_) | ZanoValue::Array(_) | ZanoValue::Function(_),
+            ZanoValue::Number(_) | ZanoValue::BigInt(_) | ZanoValue::String(_),
+        ) => loose_equals(&to_primitive(a), b),
+        (
+            ZanoValue::Number(_) | ZanoValue::BigInt(_) | ZanoValue::String(_),
+            ZanoValue::Object(_) | ZanoValue::Array(_) | ZanoValue::Function(_),
+        ) => loose_equals(a, &to_primitive(b)),
+        _ => false,
+    }
+}
+
+/// The relational comparison used by `<`, `>`, `<=` and `>=`. Two strings
+/// compare by UTF-16 code units; anything else compares numerically, and
+/// `None` means a NaN was involved so every comparison is false.
+pub fn compare(a: &ZanoValue, b: &ZanoValue) -> Option<Ordering> {
+    match (to_primitive(a), to_primitive(b)) {
+        (ZanoValue::String(x), ZanoValue::String(y)) => Some(x.encode_utf16().cmp(y.encode_utf16())),
+        (ZanoValue::BigInt(x), ZanoValue::BigInt(y)) => Some(x.cmp(&y)),
+        (ZanoValue::BigInt(x), ZanoValue::String(y)) => Some(x.cmp(&string_to_bigint(&y)?)),
+        (ZanoValue::String(x), ZanoValue::BigInt(y)) => Some(string_to_bigint(&x)?.cmp(&y)),
+        (ZanoValue::BigInt(x), y) => compare_bigint_to_number(&x, to_number(&y)),
+        (x, ZanoValue::BigInt(y)) => compare_bigint_to_number(&y, to_number(&x)).map(Ordering::reverse),
+        (x, y) => to_number(&x).partial_cmp(&to_number(&y)),
+    }
+}
+
+// Compares exactly, where converting the BigInt to a float could round
+fn compare_bigint_to_number(x: &BigInt, y: f64) -> Option<Ordering> {
+    if y.is_nan() {
+        return None;
+    }
+    if y.is_infinite() {
+        return Some(if y > 0.0 { Ordering::Less } else { Ordering::Greater });
+    }
+    let floor = BigInt::from_f64(y.floor())?;
+    let fraction = if y.fract() != 0.0 { Ordering::Less } else { Ordering::Equal };
+    Some(x.cmp(&floor).then(fraction))
+}
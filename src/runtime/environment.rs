@@ -0,0 +1,119 @@
+use crate::parser::ZanoValue;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A single lexical scope. Scopes are linked to their enclosing scope through
+/// `parent`, so lookups walk outwards until the global scope is reached.
+pub struct Environment {
+    values: RwLock<HashMap<String, Binding>>,
+    parent: Option<Arc<Environment>>,
+    is_function_scope: bool,
+}
+
+struct Binding {
+    value: ZanoValue,
+    mutable: bool,
+}
+
+impl Environment {
+    /// The outermost scope. It also acts as the function scope for top-level `var`s.
+    pub fn global() -> Arc<Self> {
+        Arc::new(Self {
+            values: RwLock::new(HashMap::new()),
+            parent: None,
+            is_function_scope: true,
+        })
+    }
+
+    /// A scope for `{ ... }` blocks, holding `let` and `const` bindings.
+    pub fn block(parent: &Arc<Self>) -> Arc<Self> {
+        Arc::new(Self {
+            values: RwLock::new(HashMap::new()),
+            parent: Some(parent.clone()),
+            is_function_scope: false,
+        })
+    }
+
+    /// A scope for a function call, holding its parameters and `var` bindings.
+    pub fn function(parent: &Arc<Self>) -> Arc<Self> {
+        Arc::new(Self {
+            values: RwLock::new(HashMap::new()),
+            parent: Some(parent.clone()),
+            is_function_scope: true,
+        })
+    }
+
+    /// Creates or replaces a binding in this scope.
+    pub async fn define(&self, name: String, value: ZanoValue) {
+        self.values.write().await.insert(name, Binding { value, mutable: true });
+    }
+
+    /// Creates a `const` binding in this scope, which `assign` refuses to change.
+    pub async fn define_const(&self, name: String, value: ZanoValue) {
+        self.values.write().await.insert(name, Binding { value, mutable: false });
+    }
+
+    /// Declares a `var` binding in the nearest enclosing function scope.
+    /// Redeclaring without an initializer keeps the existing value.
+    pub async fn define_var(&self, name: String, value: Option<ZanoValue>) {
+        let mut scope = self;
+        while !scope.is_function_scope {
+            match &scope.parent {
+                Some(parent) => scope = parent,
+                None => break,
+            }
+        }
+
+        let mut values = scope.values.write().await;
+        match value {
+            Some(value) => {
+                values.insert(name, Binding { value, mutable: true });
+            }
+            None => {
+                values.entry(name).or_insert(Binding { value: ZanoValue::Undefined, mutable: true });
+            }
+        }
+    }
+
+    /// Looks a name up through the scope chain.
+    pub async fn get(&self, name: &str) -> Option<ZanoValue> {
+        let mut scope = self;
+        loop {
+            if let Some(binding) = scope.values.read().await.get(name) {
+                return Some(binding.value.clone());
+            }
+            match &scope.parent {
+                Some(parent) => scope = parent,
+                None => return None,
+            }
+        }
+    }
+
+    /// Updates the nearest existing binding for `name`. Assigning to a name
+    /// that was never declared creates it in the global scope, as sloppy-mode
+    /// JavaScript does. Assigning to a `const` binding is an error.
+    pub async fn assign(&self, name: &str, value: ZanoValue) -> Result<()> {
+        let mut scope = self;
+        loop {
+            {
+                let mut values = scope.values.write().await;
+                if let Some(binding) = values.get_mut(name) {
+                    if !binding.mutable {
+                        return Err(super::errors::type_error(format!("Assignment to constant variable '{}'", name)));
+                    }
+                    binding.value = value;
+                    return Ok(());
+                }
+            }
+            match &scope.parent {
+                Some(parent) => scope = parent,
+                None => break,
+            }
+        }
+
+        scope.values.write().await.insert(name.to_string(), Binding { value, mutable: true });
+        Ok(())
+    }
+}
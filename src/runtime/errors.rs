@@ -0,0 +1,140 @@
+use crate::parser::{ObjectData, Shared, Span, ZanoValue};
+use crate::runtime::coercion;
+use indexmap::IndexMap;
+use std::fmt;
+
+// Error objects, and the errors that carry them through the runtime
+
+/// The built-in error constructors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorType {
+    Error,
+    TypeError,
+    RangeError,
+    SyntaxError,
+    ReferenceError,
+}
+
+impl ErrorType {
+    pub const ALL: [ErrorType; 5] = [
+        ErrorType::Error,
+        ErrorType::TypeError,
+        ErrorType::RangeError,
+        ErrorType::SyntaxError,
+        ErrorType::ReferenceError,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorType::Error => "Error",
+            ErrorType::TypeError => "TypeError",
+            ErrorType::RangeError => "RangeError",
+            ErrorType::SyntaxError => "SyntaxError",
+            ErrorType::ReferenceError => "ReferenceError",
+        }
+    }
+}
+
+/// A failure inside the runtime. Scripts see it as an error object of
+/// `error_type`; any other `anyhow` error becomes a plain `Error`.
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub error_type: ErrorType,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type.name(), self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+pub fn type_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(RuntimeError { error_type: ErrorType::TypeError, message: message.into() })
+}
+
+pub fn range_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(RuntimeError { error_type: ErrorType::RangeError, message: message.into() })
+}
+
+pub fn syntax_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(RuntimeError { error_type: ErrorType::SyntaxError, message: message.into() })
+}
+
+pub fn reference_error(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(RuntimeError { error_type: ErrorType::ReferenceError, message: message.into() })
+}
+
+/// A value on its way from a `throw`, or from a failure in the runtime, to
+/// the `catch` that receives it.
+#[derive(Debug)]
+pub struct Thrown(pub ZanoValue);
+
+impl fmt::Display for Thrown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Uncaught {}", coercion::to_js_string(&self.0))
+    }
+}
+
+impl std::error::Error for Thrown {}
+
+/// A call in progress: the function that was called and where from.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
+}
+
+/// Makes an error object with the properties the Error constructors set.
+pub fn create_error(error_type: ErrorType, message: String, cause: Option<ZanoValue>, stack: String) -> ZanoValue {
+    let mut properties = IndexMap::new();
+    properties.insert("name".to_string(), ZanoValue::String(error_type.name().to_string()));
+    properties.insert("message".to_string(), ZanoValue::String(message));
+    if let Some(cause) = cause {
+        properties.insert("cause".to_string(), cause);
+    }
+    properties.insert("stack".to_string(), ZanoValue::String(stack));
+    ZanoValue::Object(Shared::new(ObjectData { properties, error_type: Some(error_type) }))
+}
+
+/// The `stack` of an error raised at `location` in the innermost of `frames`:
+/// the error's summary, then where each active call had got to, innermost
+/// first. Like V8, it stops after `STACK_TRACE_LIMIT` calls.
+///
+/// ```text
+/// TypeError: Cannot read properties of undefined (reading 'name')
+///     at greet (script.zn:2:17)
+///     at <anonymous> (script.zn:5:1)
+/// ```
+pub fn stack_trace(summary: &str, frames: &[Frame], location: Span, file_name: &str) -> String {
+    let mut lines = vec![summary.to_string()];
+    let mut location = location;
+    for frame in frames.iter().rev().take(STACK_TRACE_LIMIT) {
+        lines.push(format!("    at {} ({}:{}:{})", frame.function, file_name, location.line, location.column));
+        location = frame.call_site;
+    }
+    if frames.len() < STACK_TRACE_LIMIT {
+        lines.push(format!("    at <anonymous> ({}:{}:{})", file_name, location.line, location.column));
+    }
+    lines.join("\n")
+}
+
+// How many lines of `at ...` a stack records, as V8's Error.stackTraceLimit
+const STACK_TRACE_LIMIT: usize = 10;
+
+/// The `at` lines of a thrown error object's stack, for reporting an error
+/// nothing caught.
+pub fn call_trace(error: &anyhow::Error) -> Option<String> {
+    let Thrown(ZanoValue::Object(object)) = error.downcast_ref::<Thrown>()? else {
+        return None;
+    };
+    let object = object.read();
+    object.error_type?;
+    let Some(ZanoValue::String(stack)) = object.properties.get("stack") else {
+        return None;
+    };
+    let lines: Vec<&str> = stack.lines().filter(|line| line.trim_start().starts_with("at ")).collect();
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
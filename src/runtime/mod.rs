@@ -1,29 +1,89 @@
-use crate::parser::{Expression, Statement, ZanoValue, BinaryOp};
+use crate::diagnostics::{self, Located};
+use crate::parser::{Expression, ExpressionKind, ObjectProperty, Parameter, Pattern, Span, Statement, StatementKind, ZanoValue, BinaryOp, UnaryOp, UpdateOp, DeclarationKind};
 use anyhow::Result;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use async_trait::async_trait;
 
+pub mod coercion;
+pub mod environment;
+pub mod errors;
 pub mod modules;
 
+use environment::Environment;
+use errors::{ErrorType, Frame, RuntimeError, Thrown};
+
 #[async_trait]
 pub trait ZanoFunction: Send + Sync {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue>;
+    
+    /// Calls the function as a method of `this`. Functions that don't use
+    /// `this` can leave this to `call`.
+    async fn call_with_this(&self, _this: ZanoValue, args: Vec<ZanoValue>) -> Result<ZanoValue> {
+        self.call(args).await
+    }
+    
+    fn name(&self) -> &str {
+        "anonymous"
+    }
+    
+    /// Whether the function can be called with `new`.
+    fn is_constructor(&self) -> bool {
+        false
+    }
+    
+    /// Whether `value instanceof` this function holds. Only the Error
+    /// constructors have instances.
+    fn has_instance(&self, _value: &ZanoValue) -> bool {
+        false
+    }
+}
+
+impl std::fmt::Debug for dyn ZanoFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[Function: {}]", self.name())
+    }
+}
+
+/// How a statement finished. Anything other than `Normal` is an abrupt
+/// completion that unwinds enclosing blocks until something handles it.
+pub enum Completion {
+    Normal(ZanoValue),
+    Return(ZanoValue),
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+impl Completion {
+    // Whether a break/continue with this label applies to a loop with `labels`
+    fn targets(label: &Option<String>, labels: &[String]) -> bool {
+        match label {
+            Some(label) => labels.contains(label),
+            None => true,
+        }
+    }
 }
 
 pub struct ZanoRuntime {
-    globals: Arc<RwLock<HashMap<String, ZanoValue>>>,
-    functions: Arc<RwLock<HashMap<String, Arc<dyn ZanoFunction>>>>,
+    globals: Arc<Environment>,
     modules: Arc<RwLock<HashMap<String, ZanoValue>>>,
+    // The calls in progress and the script being run, for error stacks
+    call_stack: Arc<std::sync::Mutex<Vec<Frame>>>,
+    file_name: Arc<std::sync::RwLock<String>>,
 }
 
 impl ZanoRuntime {
     pub async fn new() -> Self {
         let runtime = Self {
-            globals: Arc::new(RwLock::new(HashMap::new())),
-            functions: Arc::new(RwLock::new(HashMap::new())),
+            globals: Environment::global(),
             modules: Arc::new(RwLock::new(HashMap::new())),
+            call_stack: Arc::new(std::sync::Mutex::new(Vec::new())),
+            file_name: Arc::new(std::sync::RwLock::new("<anonymous>".to_string())),
         };
         
         // Initialize built-ins
@@ -35,292 +95,1182 @@ impl ZanoRuntime {
     async fn init_builtins(&self) {
         use crate::runtime::modules::*;
         
-        // Initialize console functions
-        self.functions.write().await.insert("console_log".to_string(), Arc::new(ConsoleLog));
-        self.functions.write().await.insert("console_error".to_string(), Arc::new(ConsoleError));
-        self.functions.write().await.insert("console_warn".to_string(), Arc::new(ConsoleWarn));
-        
-        // Initialize fs functions
-        self.functions.write().await.insert("fs_readFile".to_string(), Arc::new(FsReadFile));
-        self.functions.write().await.insert("fs_writeFile".to_string(), Arc::new(FsWriteFile));
-        self.functions.write().await.insert("fs_exists".to_string(), Arc::new(FsExists));
-        
-        // Initialize http functions
-        self.functions.write().await.insert("http_createServer".to_string(), Arc::new(HttpCreateServer));
-        self.functions.write().await.insert("http_request".to_string(), Arc::new(HttpRequest));
-        
-        // Initialize path functions
-        self.functions.write().await.insert("path_join".to_string(), Arc::new(PathJoin));
-        self.functions.write().await.insert("path_dirname".to_string(), Arc::new(PathDirname));
-        self.functions.write().await.insert("path_basename".to_string(), Arc::new(PathBasename));
-        
         // Create module system
         let module_system = modules::ModuleSystem::new();
         module_system.init(self).await.expect("Failed to initialize modules");
         
         // Add global console object
-        let mut console_obj = HashMap::new();
-        console_obj.insert("log".to_string(), ZanoValue::Function("console_log".to_string()));
-        console_obj.insert("error".to_string(), ZanoValue::Function("console_error".to_string()));
-        console_obj.insert("warn".to_string(), ZanoValue::Function("console_warn".to_string()));
-        self.globals.write().await.insert("console".to_string(), ZanoValue::Object(console_obj));
+        if let Some(console_obj) = module_system.get_module("console").await {
+            self.globals.define("console".to_string(), console_obj).await;
+        }
         
         // Add require function
-        self.functions.write().await.insert("require".to_string(), Arc::new(RequireFunction::new(module_system)));
-        self.globals.write().await.insert("require".to_string(), ZanoValue::Function("require".to_string()));
+        let require = ZanoValue::Function(Arc::new(RequireFunction::new(module_system)));
+        self.globals.define("require".to_string(), require).await;
+        
+        self.globals.define_const("NaN".to_string(), ZanoValue::Number(f64::NAN)).await;
+        self.globals.define_const("Infinity".to_string(), ZanoValue::Number(f64::INFINITY)).await;
+        
+        for error_type in ErrorType::ALL {
+            let constructor = ErrorConstructor { error_type, runtime: self.clone() };
+            self.globals.define(error_type.name().to_string(), ZanoValue::Function(Arc::new(constructor))).await;
+        }
+    }
+    
+    /// Sets the script name that error stacks refer to.
+    pub fn set_file_name(&self, file_name: &str) {
+        *self.file_name.write().unwrap() = file_name.to_string();
+    }
+    
+    /// Makes an error object whose stack starts at `location`, inside the
+    /// innermost of `frames`.
+    fn create_error(&self, error_type: ErrorType, message: String, cause: Option<ZanoValue>, location: Span, frames: &[Frame]) -> ZanoValue {
+        let summary = if message.is_empty() {
+            error_type.name().to_string()
+        } else {
+            format!("{}: {}", error_type.name(), message)
+        };
+        let stack = errors::stack_trace(&summary, frames, location, &self.file_name.read().unwrap());
+        errors::create_error(error_type, message, cause, stack)
+    }
+    
+    fn frames(&self) -> Vec<Frame> {
+        self.call_stack.lock().unwrap().clone()
+    }
+    
+    /// Attaches `span` to an error raised while evaluating the node there.
+    /// Failures inside the runtime become error objects at this point, while
+    /// the call that hit them is still on the stack.
+    fn locate(&self, error: anyhow::Error, span: Span) -> anyhow::Error {
+        if error.is::<Located>() || error.is::<Thrown>() {
+            return diagnostics::locate(error, span);
+        }
+        let (error_type, message) = match error.downcast_ref::<RuntimeError>() {
+            Some(runtime_error) => (runtime_error.error_type, runtime_error.message.clone()),
+            None => (ErrorType::Error, error.to_string()),
+        };
+        let value = self.create_error(error_type, message, None, span, &self.frames());
+        diagnostics::locate(anyhow::Error::new(Thrown(value)), span)
+    }
+    
+    /// The value a `catch` receives for `error`.
+    fn thrown_value(error: &anyhow::Error) -> ZanoValue {
+        let error = match error.downcast_ref::<Located>() {
+            Some(located) => &located.error,
+            None => error,
+        };
+        match error.downcast_ref::<Thrown>() {
+            Some(Thrown(value)) => value.clone(),
+            None => ZanoValue::String(error.to_string()),
+        }
+    }
+    
+    // How deeply calls may nest before throwing, rather than overflowing
+    // the native stack the interpreter recurses on
+    const MAX_CALL_DEPTH: usize = 1000;
+    
+    // How much native stack must be left to evaluate another statement or
+    // expression. Nesting inside one function uses the stack without adding
+    // calls, so the call depth alone can't rule out an overflow.
+    const STACK_RED_ZONE: usize = 4 * 1024 * 1024;
+    
+    /// Hands `node` back if there is stack left to evaluate it. Otherwise the
+    /// node is dropped on a thread of its own, as the rest of a deeply nested
+    /// tree can be too deep to drop on what is left of this stack.
+    fn check_stack<T: Send + 'static>(node: T) -> Result<T> {
+        if stacker::remaining_stack().is_some_and(|left| left < Self::STACK_RED_ZONE) {
+            let _ = std::thread::Builder::new()
+                .stack_size(crate::INTERPRETER_STACK_SIZE)
+                .spawn(move || drop(node));
+            return Err(errors::range_error("Maximum call stack size exceeded"));
+        }
+        Ok(node)
+    }
+    
+    /// Calls `func` with a frame on the call stack for the duration.
+    async fn call_function(&self, func: &Arc<dyn ZanoFunction>, this: ZanoValue, args: Vec<ZanoValue>, description: String, call_site: Span) -> Result<ZanoValue> {
+        {
+            let mut call_stack = self.call_stack.lock().unwrap();
+            if call_stack.len() >= Self::MAX_CALL_DEPTH {
+                return Err(errors::range_error("Maximum call stack size exceeded"));
+            }
+            call_stack.push(Frame { function: description, call_site });
+        }
+        let result = func.call_with_this(this, args).await;
+        self.call_stack.lock().unwrap().pop();
+        result
     }
     
     pub async fn execute(&self, statements: Vec<Statement>) -> Result<ZanoValue> {
         let mut last_value = ZanoValue::Undefined;
+        self.hoist(&statements, &self.globals).await;
         
         for statement in statements {
-            last_value = self.execute_statement(statement).await?;
+            match self.execute_statement(statement, self.globals.clone()).await? {
+                Completion::Normal(value) => last_value = value,
+                Completion::Return(value) => return Ok(value),
+                // The parser rejects break/continue outside of a loop
+                Completion::Break(_) | Completion::Continue(_) => {}
+            }
         }
         
         Ok(last_value)
     }
     
-    fn execute_statement(&self, statement: Statement) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ZanoValue>> + Send + '_>> {
+    fn execute_statement(&self, statement: Statement, env: Arc<Environment>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Completion>> + Send + '_>> {
         Box::pin(async move {
-        match statement {
-            Statement::Expression(expr) => self.evaluate_expression(expr).await,
-            Statement::VarDeclaration { name, value, is_const: _ } => {
-                let val = if let Some(expr) = value {
-                    self.evaluate_expression(expr).await?
-                } else {
-                    ZanoValue::Undefined
-                };
-                
-                self.globals.write().await.insert(name, val);
-                Ok(ZanoValue::Undefined)
-            }
-            Statement::FunctionDeclaration { name, params, body, is_async: _ } => {
-                let func = UserDefinedFunction {
-                    params,
-                    body,
-                    runtime: self.clone(),
-                };
-                
-                self.functions.write().await.insert(name.clone(), Arc::new(func));
-                self.globals.write().await.insert(name, ZanoValue::Function("user_defined".to_string()));
-                
-                Ok(ZanoValue::Undefined)
+        let span = statement.span;
+        let result: Result<Completion> = async move {
+        let statement = Self::check_stack(statement)?;
+        match statement.kind {
+            StatementKind::Expression(expr) => Ok(Completion::Normal(self.evaluate_expression(expr, env).await?)),
+            StatementKind::VarDeclaration { target, value, kind } => {
+                match value {
+                    Some(expr) => {
+                        let val = self.evaluate_expression(expr, env.clone()).await?;
+                        self.bind_pattern(&target, val, &env, Some(kind)).await?;
+                    }
+                    // The parser only allows a plain name without an initializer
+                    None => {
+                        for name in target.names() {
+                            match kind {
+                                DeclarationKind::Var => env.define_var(name, None).await,
+                                _ => env.define(name, ZanoValue::Undefined).await,
+                            }
+                        }
+                    }
+                }
+                Ok(Completion::Normal(ZanoValue::Undefined))
             }
-            Statement::If { condition, then_branch, else_branch } => {
-                let condition_value = self.evaluate_expression(condition).await?;
+            // Defined by hoist_functions when its scope was entered
+            StatementKind::FunctionDeclaration { .. } => Ok(Completion::Normal(ZanoValue::Undefined)),
+            StatementKind::If { condition, then_branch, else_branch } => {
+                let condition_value = self.evaluate_expression(condition, env.clone()).await?;
                 
                 if self.is_truthy(&condition_value) {
-                    self.execute_statement(*then_branch).await
+                    self.execute_statement(*then_branch, env).await
                 } else if let Some(else_stmt) = else_branch {
-                    self.execute_statement(*else_stmt).await
+                    self.execute_statement(*else_stmt, env).await
                 } else {
-                    Ok(ZanoValue::Undefined)
+                    Ok(Completion::Normal(ZanoValue::Undefined))
                 }
             }
-            Statement::Block(statements) => {
-                let mut last_value = ZanoValue::Undefined;
-                for stmt in statements {
-                    last_value = self.execute_statement(stmt).await?;
-                }
-                Ok(last_value)
+            StatementKind::Block(statements) => {
+                let block_env = Environment::block(&env);
+                self.hoist_functions(&statements, &block_env).await;
+                self.execute_block(statements, block_env).await
             }
-            Statement::Return(expr) => {
-                if let Some(expression) = expr {
-                    self.evaluate_expression(expression).await
+            StatementKind::Return(expr) => {
+                let value = if let Some(expression) = expr {
+                    self.evaluate_expression(expression, env).await?
                 } else {
-                    Ok(ZanoValue::Undefined)
+                    ZanoValue::Undefined
+                };
+                Ok(Completion::Return(value))
+            }
+            kind @ (StatementKind::While { .. } | StatementKind::DoWhile { .. } | StatementKind::For { .. }
+                | StatementKind::ForOf { .. } | StatementKind::ForIn { .. }) => {
+                self.execute_loop(Statement { kind, span }, &[], env).await
+            }
+            StatementKind::Switch { discriminant, cases } => {
+                let value = self.evaluate_expression(discriminant, env.clone()).await?;
+                let switch_env = Environment::block(&env);
+                for case in &cases {
+                    self.hoist_functions(&case.body, &switch_env).await;
+                }
+                
+                // Cases are tested in order with ===, and default is only taken
+                // when none match, wherever it appears
+                let mut start = None;
+                for (index, case) in cases.iter().enumerate() {
+                    if let Some(test) = &case.test {
+                        let test = self.evaluate_expression(test.clone(), switch_env.clone()).await?;
+                        if coercion::strict_equals(&value, &test) {
+                            start = Some(index);
+                            break;
+                        }
+                    }
                 }
+                let Some(start) = start.or_else(|| cases.iter().position(|case| case.test.is_none())) else {
+                    return Ok(Completion::Normal(ZanoValue::Undefined));
+                };
+                
+                // Execution falls through into the following cases until a break
+                for case in cases.into_iter().skip(start) {
+                    match self.execute_block(case.body, switch_env.clone()).await? {
+                        Completion::Normal(_) => {}
+                        Completion::Break(None) => break,
+                        abrupt => return Ok(abrupt),
+                    }
+                }
+                Ok(Completion::Normal(ZanoValue::Undefined))
             }
-            Statement::While { condition, body } => {
-                while self.is_truthy(&self.evaluate_expression(condition.clone()).await?) {
-                    self.execute_statement((*body).clone()).await?;
+            StatementKind::Break(label) => Ok(Completion::Break(label)),
+            StatementKind::Continue(label) => Ok(Completion::Continue(label)),
+            StatementKind::Labeled { label, body } => {
+                // Collect stacked labels so the loop they name can match them
+                let mut labels = vec![label];
+                let mut body = *body;
+                while let StatementKind::Labeled { label, body: inner } = body.kind {
+                    labels.push(label);
+                    body = *inner;
+                }
+                
+                let completion = if body.is_loop() {
+                    self.execute_loop(body, &labels, env).await?
+                } else {
+                    self.execute_statement(body, env).await?
+                };
+                
+                match completion {
+                    Completion::Break(Some(label)) if labels.contains(&label) => {
+                        Ok(Completion::Normal(ZanoValue::Undefined))
+                    }
+                    other => Ok(other),
                 }
-                Ok(ZanoValue::Undefined)
             }
-            Statement::Try { try_block, catch_param, catch_block } => {
-                match self.execute_statement(*try_block).await {
-                    Ok(value) => Ok(value),
+            StatementKind::Try { try_block, catch_param, catch_block, finally_block } => {
+                let result = match self.execute_statement(*try_block, env.clone()).await {
+                    Ok(completion) => Ok(completion),
                     Err(error) => {
                         if let Some(catch_stmt) = catch_block {
-                            if let Some(param_name) = catch_param {
-                                // Bind error to catch parameter
-                                let error_obj = ZanoValue::String(error.to_string());
-                                self.globals.write().await.insert(param_name, error_obj);
-                            }
-                            self.execute_statement(*catch_stmt).await
+                            let catch_env = Environment::block(&env);
+                            async {
+                                if let Some(pattern) = catch_param {
+                                    self.bind_pattern(&pattern, Self::thrown_value(&error), &catch_env, Some(DeclarationKind::Let)).await?;
+                                }
+                                self.execute_statement(*catch_stmt, catch_env.clone()).await
+                            }.await
                         } else {
                             Err(error)
                         }
                     }
+                };
+                
+                let Some(finally_block) = finally_block else {
+                    return result;
+                };
+                // finally always runs. If it completes normally the outcome of
+                // try/catch stands; a return, break, continue or throw inside
+                // it replaces that outcome, even a pending error.
+                match self.execute_statement(*finally_block, env).await? {
+                    Completion::Normal(_) => result,
+                    abrupt => Ok(abrupt),
                 }
             }
-            Statement::Throw(expr) => {
-                let value = self.evaluate_expression(expr).await?;
-                let error_message = match value {
-                    ZanoValue::String(s) => s,
-                    _ => format!("{:?}", value),
-                };
-                Err(anyhow::anyhow!("Thrown: {}", error_message))
+            StatementKind::Throw(expr) => {
+                let value = self.evaluate_expression(expr, env).await?;
+                Err(anyhow::Error::new(Thrown(value)))
             }
         }
+        }.await;
+        result.map_err(|error| self.locate(error, span))
         })
     }
     
-    fn evaluate_expression(&self, expression: Expression) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ZanoValue>> + Send + '_>> {
-        Box::pin(async move {
-        match expression {
-            Expression::Literal(value) => Ok(value),
-            Expression::Identifier(name) => {
-                if let Some(value) = self.globals.read().await.get(&name) {
-                    Ok(value.clone())
-                } else {
-                    Err(anyhow::anyhow!("Undefined variable: {}", name))
+    // Runs a loop statement. `labels` are the labels attached to it, which
+    // labelled break/continue statements in the body may target.
+    async fn execute_loop(&self, statement: Statement, labels: &[String], env: Arc<Environment>) -> Result<Completion> {
+        let span = statement.span;
+        match statement.kind {
+            StatementKind::While { condition, body } => {
+                while self.is_truthy(&self.evaluate_expression(condition.clone(), env.clone()).await?) {
+                    match self.execute_statement((*body).clone(), env.clone()).await? {
+                        Completion::Break(label) if Completion::targets(&label, labels) => break,
+                        Completion::Continue(label) if Completion::targets(&label, labels) => continue,
+                        Completion::Normal(_) => {}
+                        abrupt => return Ok(abrupt),
+                    }
+                }
+                Ok(Completion::Normal(ZanoValue::Undefined))
+            }
+            StatementKind::DoWhile { body, condition } => {
+                loop {
+                    match self.execute_statement((*body).clone(), env.clone()).await? {
+                        Completion::Break(label) if Completion::targets(&label, labels) => break,
+                        Completion::Continue(label) if Completion::targets(&label, labels) => {}
+                        Completion::Normal(_) => {}
+                        abrupt => return Ok(abrupt),
+                    }
+                    if !self.is_truthy(&self.evaluate_expression(condition.clone(), env.clone()).await?) {
+                        break;
+                    }
                 }
+                Ok(Completion::Normal(ZanoValue::Undefined))
             }
-            Expression::Binary { left, operator, right } => {
-                let left_val = self.evaluate_expression(*left).await?;
-                let right_val = self.evaluate_expression(*right).await?;
+            StatementKind::For { init, condition, update, body } => {
+                let loop_env = Environment::block(&env);
                 
-                self.apply_binary_operator(left_val, operator, right_val)
-            }
-            Expression::Call { callee, args } => {
-                let function_name = match *callee {
-                    Expression::Identifier(name) => name,
-                    Expression::Member { object, property } => {
-                        // Handle member function calls like console.log
-                        match *object {
-                            Expression::Identifier(obj_name) => {
-                                format!("{}_{}", obj_name, property)
-                            }
-                            _ => return Err(anyhow::anyhow!("Complex member calls not supported yet")),
+                // let/const bindings from the head are copied into a fresh scope
+                // for every iteration, so closures see that iteration's value
+                let mut per_iteration = Vec::new();
+                if let Some(init) = init {
+                    if let StatementKind::VarDeclaration { target, kind, .. } = &init.kind {
+                        if *kind != DeclarationKind::Var {
+                            per_iteration.extend(target.names().into_iter().map(|name| (name, *kind)));
                         }
                     }
-                    _ => return Err(anyhow::anyhow!("Invalid function call")),
-                };
+                    self.execute_statement(*init, loop_env.clone()).await?;
+                }
                 
-                let mut arg_values = Vec::new();
-                for arg in args {
-                    arg_values.push(self.evaluate_expression(arg).await?);
+                let mut iteration_env = Self::iteration_env(&per_iteration, &loop_env, &loop_env).await;
+                loop {
+                    if let Some(condition) = &condition {
+                        if !self.is_truthy(&self.evaluate_expression(condition.clone(), iteration_env.clone()).await?) {
+                            break;
+                        }
+                    }
+                    
+                    match self.execute_statement((*body).clone(), iteration_env.clone()).await? {
+                        Completion::Break(label) if Completion::targets(&label, labels) => break,
+                        Completion::Continue(label) if Completion::targets(&label, labels) => {}
+                        Completion::Normal(_) => {}
+                        abrupt => return Ok(abrupt),
+                    }
+                    
+                    iteration_env = Self::iteration_env(&per_iteration, &iteration_env, &loop_env).await;
+                    if let Some(update) = &update {
+                        self.evaluate_expression(update.clone(), iteration_env.clone()).await?;
+                    }
                 }
+                Ok(Completion::Normal(ZanoValue::Undefined))
+            }
+            StatementKind::ForOf { declaration, target, iterable, body } => {
+                let description = Self::describe_expression(&iterable);
+                let iterable = self.evaluate_expression(iterable, env.clone()).await?;
+                let values = Self::iterate(&iterable, &description)?;
                 
-                if let Some(func) = self.functions.read().await.get(&function_name) {
-                    func.call(arg_values).await
-                } else {
-                    Err(anyhow::anyhow!("Undefined function: {}", function_name))
+                for value in values {
+                    let body_env = self.bind_loop_variable(declaration, &target, value, &env).await?;
+                    match self.execute_statement((*body).clone(), body_env).await? {
+                        Completion::Break(label) if Completion::targets(&label, labels) => break,
+                        Completion::Continue(label) if Completion::targets(&label, labels) => continue,
+                        Completion::Normal(_) => {}
+                        abrupt => return Ok(abrupt),
+                    }
                 }
+                Ok(Completion::Normal(ZanoValue::Undefined))
             }
-            Expression::Member { object, property } => {
-                let obj_value = self.evaluate_expression(*object).await?;
+            StatementKind::ForIn { declaration, target, object, body } => {
+                let keys: Vec<String> = match self.evaluate_expression(object, env.clone()).await? {
+                    ZanoValue::Object(object) => object.read().properties.keys().cloned().collect(),
+                    ZanoValue::Array(array) => {
+                        let array = array.read();
+                        array.entries().map(|(index, _)| index.to_string()).chain(array.properties.keys().cloned()).collect()
+                    }
+                    ZanoValue::String(s) => (0..s.encode_utf16().count()).map(|i| i.to_string()).collect(),
+                    _ => Vec::new(),
+                };
                 
-                match obj_value {
-                    ZanoValue::Object(ref map) => {
-                        if let Some(value) = map.get(&property) {
-                            Ok(value.clone())
-                        } else {
-                            Ok(ZanoValue::Undefined)
-                        }
+                for key in keys {
+                    let body_env = self.bind_loop_variable(declaration, &target, ZanoValue::String(key), &env).await?;
+                    match self.execute_statement((*body).clone(), body_env).await? {
+                        Completion::Break(label) if Completion::targets(&label, labels) => break,
+                        Completion::Continue(label) if Completion::targets(&label, labels) => continue,
+                        Completion::Normal(_) => {}
+                        abrupt => return Ok(abrupt),
                     }
-                    _ => Ok(ZanoValue::Undefined),
                 }
+                Ok(Completion::Normal(ZanoValue::Undefined))
             }
-            Expression::Assignment { target, value } => {
-                let val = self.evaluate_expression(*value).await?;
-                self.globals.write().await.insert(target, val.clone());
-                Ok(val)
+            kind => self.execute_statement(Statement { kind, span }, env).await,
+        }
+    }
+    
+    // Creates the scope for the next iteration of a C-style for loop, carrying
+    // over the current values of the head's let/const bindings
+    async fn iteration_env(bindings: &[(String, DeclarationKind)], current: &Arc<Environment>, loop_env: &Arc<Environment>) -> Arc<Environment> {
+        if bindings.is_empty() {
+            return current.clone();
+        }
+        
+        let next = Environment::block(loop_env);
+        for (name, kind) in bindings {
+            let value = current.get(name).await.unwrap_or(ZanoValue::Undefined);
+            if *kind == DeclarationKind::Const {
+                next.define_const(name.clone(), value).await;
+            } else {
+                next.define(name.clone(), value).await;
+            }
+        }
+        next
+    }
+    
+    // Binds the variable of a for...in/for...of iteration and returns the scope
+    // the loop body should run in
+    async fn bind_loop_variable(&self, declaration: Option<DeclarationKind>, target: &Pattern, value: ZanoValue, env: &Arc<Environment>) -> Result<Arc<Environment>> {
+        let body_env = match declaration {
+            Some(DeclarationKind::Let | DeclarationKind::Const) => Environment::block(env),
+            _ => env.clone(),
+        };
+        self.bind_pattern(target, value, &body_env, declaration).await?;
+        Ok(body_env)
+    }
+    
+    /// Binds the names in `pattern` to the parts of `value` they pick out, as
+    /// a declaration of kind `declaration` would. Without a declaration the
+    /// names are assigned to instead.
+    fn bind_pattern<'a>(&'a self, pattern: &'a Pattern, value: ZanoValue, env: &'a Arc<Environment>, declaration: Option<DeclarationKind>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+        match pattern {
+            Pattern::Identifier(name) => {
+                match declaration {
+                    Some(DeclarationKind::Var) => env.define_var(name.clone(), Some(value)).await,
+                    Some(DeclarationKind::Let) => env.define(name.clone(), value).await,
+                    Some(DeclarationKind::Const) => env.define_const(name.clone(), value).await,
+                    None => env.assign(name, value).await?,
+                }
+            }
+            Pattern::Object { properties, rest } => {
+                if matches!(value, ZanoValue::Null | ZanoValue::Undefined) {
+                    let value = coercion::to_js_string(&value);
+                    return Err(errors::type_error(format!("Cannot destructure '{}' as it is {}", value, value)));
+                }
+                for (key, element) in properties {
+                    let property = self.get_property(&value, &ZanoValue::String(key.clone()))?;
+                    let property = self.apply_default(&element.default, property, env).await?;
+                    self.bind_pattern(&element.target, property, env, declaration).await?;
+                }
+                if let Some(rest) = rest {
+                    let remaining: IndexMap<String, ZanoValue> = Self::own_properties(&value)
+                        .into_iter()
+                        .filter(|(key, _)| !properties.iter().any(|(taken, _)| taken == key))
+                        .collect();
+                    let rest_pattern = Pattern::Identifier(rest.clone());
+                    self.bind_pattern(&rest_pattern, ZanoValue::new_object(remaining), env, declaration).await?;
+                }
             }
-            Expression::Array(elements) => {
-                let mut values = Vec::new();
+            Pattern::Array { elements, rest } => {
+                let mut items = Self::iterate(&value, &coercion::to_js_string(&value))?.into_iter();
                 for element in elements {
-                    values.push(self.evaluate_expression(element).await?);
+                    let item = items.next().unwrap_or(ZanoValue::Undefined);
+                    if let Some(element) = element {
+                        let item = self.apply_default(&element.default, item, env).await?;
+                        self.bind_pattern(&element.target, item, env, declaration).await?;
+                    }
+                }
+                if let Some(rest) = rest {
+                    self.bind_pattern(rest, ZanoValue::new_array(items.collect()), env, declaration).await?;
                 }
-                Ok(ZanoValue::Array(values))
             }
-            Expression::Object(pairs) => {
-                let mut obj = HashMap::new();
-                for (key, value) in pairs {
-                    let val = self.evaluate_expression(value).await?;
-                    obj.insert(key, val);
+            Pattern::Target(target) => {
+                let reference = self.resolve_reference((**target).clone(), env.clone()).await?;
+                self.put_value(&reference, value, env).await?;
+            }
+        }
+        Ok(())
+        })
+    }
+    
+    // Replaces an undefined value with the evaluated default, if there is one
+    async fn apply_default(&self, default: &Option<Expression>, value: ZanoValue, env: &Arc<Environment>) -> Result<ZanoValue> {
+        match (default, value) {
+            (Some(default), ZanoValue::Undefined) => self.evaluate_expression(default.clone(), env.clone()).await,
+            (_, value) => Ok(value),
+        }
+    }
+    
+    // Hoisting, on entering a program or function body: its `var` names
+    // exist as undefined from the start, and its functions can be called
+    // before their declarations
+    async fn hoist(&self, statements: &[Statement], env: &Arc<Environment>) {
+        let mut names = Vec::new();
+        for statement in statements {
+            statement.var_names(&mut names);
+        }
+        for name in names {
+            env.define_var(name, None).await;
+        }
+        self.hoist_functions(statements, env).await;
+    }
+    
+    // Defines the function declarations directly in a block or body
+    async fn hoist_functions(&self, statements: &[Statement], env: &Arc<Environment>) {
+        for statement in statements {
+            let mut statement = statement;
+            while let StatementKind::Labeled { body, .. } = &statement.kind {
+                statement = body;
+            }
+            if let StatementKind::FunctionDeclaration { name, params, body, .. } = &statement.kind {
+                let func = UserDefinedFunction {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: env.clone(),
+                    runtime: self.clone(),
+                    is_arrow: false,
+                };
+                env.define(name.clone(), ZanoValue::Function(Arc::new(func))).await;
+            }
+        }
+    }
+    
+    // Runs statements in an already-created scope, stopping early on any
+    // abrupt completion so it can propagate to the enclosing loop or call
+    async fn execute_block(&self, statements: Vec<Statement>, env: Arc<Environment>) -> Result<Completion> {
+        let mut last_value = ZanoValue::Undefined;
+        for stmt in statements {
+            match self.execute_statement(stmt, env.clone()).await? {
+                Completion::Normal(value) => last_value = value,
+                abrupt => return Ok(abrupt),
+            }
+        }
+        Ok(Completion::Normal(last_value))
+    }
+    
+    fn evaluate_expression(&self, expression: Expression, env: Arc<Environment>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ZanoValue>> + Send + '_>> {
+        Box::pin(async move {
+        let span = expression.span;
+        let result: Result<ZanoValue> = async move {
+        let expression = Self::check_stack(expression)?;
+        match expression.kind {
+            ExpressionKind::Literal(value) => Ok(value),
+            ExpressionKind::This => Ok(env.get("this").await.unwrap_or(ZanoValue::Undefined)),
+            ExpressionKind::Function { name, params, body, is_arrow } => {
+                // A named function expression can refer to itself by name,
+                // from a scope of its own between it and its surroundings
+                let closure = match &name {
+                    Some(_) => Environment::block(&env),
+                    None => env,
+                };
+                let func: Arc<dyn ZanoFunction> = Arc::new(UserDefinedFunction {
+                    name: name.clone().unwrap_or_else(|| "anonymous".to_string()),
+                    params,
+                    body,
+                    closure: closure.clone(),
+                    runtime: self.clone(),
+                    is_arrow,
+                });
+                if let Some(name) = name {
+                    closure.define_const(name, ZanoValue::Function(func.clone())).await;
                 }
-                Ok(ZanoValue::Object(obj))
+                Ok(ZanoValue::Function(func))
             }
-            Expression::Index { object, index } => {
-                let obj_value = self.evaluate_expression(*object).await?;
-                let index_value = self.evaluate_expression(*index).await?;
+            ExpressionKind::Identifier(name) => {
+                if let Some(value) = env.get(&name).await {
+                    Ok(value)
+                } else {
+                    Err(errors::reference_error(format!("Undefined variable: {}", name)))
+                }
+            }
+            ExpressionKind::Unary { operator, operand } => self.evaluate_unary(operator, *operand, env).await,
+            ExpressionKind::Binary { left, operator, right } => {
+                let left_val = self.evaluate_expression(*left, env.clone()).await?;
                 
-                match (obj_value, index_value) {
-                    (ZanoValue::Array(ref arr), ZanoValue::Number(n)) => {
-                        let idx = n as usize;
-                        if idx < arr.len() {
-                            Ok(arr[idx].clone())
-                        } else {
-                            Ok(ZanoValue::Undefined)
-                        }
+                // Logical operators only evaluate the right side when needed
+                // and produce one of the operand values, not a boolean
+                let short_circuits = match operator {
+                    BinaryOp::And => !self.is_truthy(&left_val),
+                    BinaryOp::Or => self.is_truthy(&left_val),
+                    BinaryOp::Nullish => !matches!(left_val, ZanoValue::Null | ZanoValue::Undefined),
+                    _ => {
+                        let right_val = self.evaluate_expression(*right, env).await?;
+                        return self.apply_binary_operator(left_val, operator, right_val);
                     }
-                    (ZanoValue::Object(ref obj), ZanoValue::String(key)) => {
-                        Ok(obj.get(&key).cloned().unwrap_or(ZanoValue::Undefined))
+                };
+                
+                if short_circuits {
+                    Ok(left_val)
+                } else {
+                    self.evaluate_expression(*right, env).await
+                }
+            }
+            kind @ (ExpressionKind::Call { .. } | ExpressionKind::Member { .. } | ExpressionKind::Index { .. }) => {
+                // Without an enclosing OptionalChain no link is optional, so
+                // this never short-circuits
+                let value = self.evaluate_chain(Expression::new(kind, span), env).await?;
+                Ok(value.unwrap_or(ZanoValue::Undefined))
+            }
+            ExpressionKind::OptionalChain(chain) => {
+                Ok(self.evaluate_chain(*chain, env).await?.unwrap_or(ZanoValue::Undefined))
+            }
+            ExpressionKind::Conditional { condition, then_branch, else_branch } => {
+                let condition = self.evaluate_expression(*condition, env.clone()).await?;
+                if self.is_truthy(&condition) {
+                    self.evaluate_expression(*then_branch, env).await
+                } else {
+                    self.evaluate_expression(*else_branch, env).await
+                }
+            }
+            ExpressionKind::Assignment { target, value } => {
+                // The target's object and key are evaluated before the value
+                let reference = self.resolve_reference(*target, env.clone()).await?;
+                let val = self.evaluate_expression(*value, env.clone()).await?;
+                self.put_value(&reference, val.clone(), &env).await?;
+                Ok(val)
+            }
+            ExpressionKind::DestructuringAssignment { target, value } => {
+                let val = self.evaluate_expression(*value, env.clone()).await?;
+                self.bind_pattern(&target, val.clone(), &env, None).await?;
+                Ok(val)
+            }
+            ExpressionKind::CompoundAssignment { target, operator, value } => {
+                let reference = self.resolve_reference(*target, env.clone()).await?;
+                let current = self.get_value(&reference, &env).await?;
+                
+                // Logical assignments only evaluate and store the value when needed
+                let short_circuit = match operator {
+                    BinaryOp::And => !self.is_truthy(&current),
+                    BinaryOp::Or => self.is_truthy(&current),
+                    BinaryOp::Nullish => !matches!(current, ZanoValue::Null | ZanoValue::Undefined),
+                    _ => false,
+                };
+                if short_circuit {
+                    return Ok(current);
+                }
+                
+                let val = self.evaluate_expression(*value, env.clone()).await?;
+                let result = match operator {
+                    BinaryOp::And | BinaryOp::Or | BinaryOp::Nullish => val,
+                    _ => self.apply_binary_operator(current, operator, val)?,
+                };
+                self.put_value(&reference, result.clone(), &env).await?;
+                Ok(result)
+            }
+            ExpressionKind::Update { operator, prefix, target } => {
+                let reference = self.resolve_reference(*target, env.clone()).await?;
+                let old = coercion::to_numeric(&self.get_value(&reference, &env).await?);
+                let one = match old {
+                    ZanoValue::BigInt(_) => ZanoValue::BigInt(BigInt::from(1)),
+                    _ => ZanoValue::Number(1.0),
+                };
+                let new = match operator {
+                    UpdateOp::Increment => Self::arithmetic(BinaryOp::Add, &old, &one)?,
+                    UpdateOp::Decrement => Self::arithmetic(BinaryOp::Sub, &old, &one)?,
+                };
+                self.put_value(&reference, new.clone(), &env).await?;
+                Ok(if prefix { new } else { old })
+            }
+            ExpressionKind::Array(elements) => {
+                let values = self.evaluate_elements(elements, env).await?;
+                Ok(ZanoValue::new_array(values))
+            }
+            ExpressionKind::Object(properties) => {
+                let mut obj = IndexMap::new();
+                for property in properties {
+                    match property {
+                        ObjectProperty::KeyValue(key, value) => {
+                            let val = self.evaluate_expression(value, env.clone()).await?;
+                            obj.insert(key, val);
+                        }
+                        ObjectProperty::Spread(source) => {
+                            let source = self.evaluate_expression(source, env.clone()).await?;
+                            obj.extend(Self::own_properties(&source));
+                        }
                     }
-                    _ => Ok(ZanoValue::Undefined),
                 }
+                Ok(ZanoValue::new_object(obj))
             }
-            Expression::Await(expr) => {
+            ExpressionKind::Spread(_) => Err(errors::syntax_error("Unexpected spread")),
+            ExpressionKind::Await(expr) => {
                 // For now, just evaluate the expression
                 // In a full implementation, this would handle promises/futures
-                self.evaluate_expression(*expr).await
+                self.evaluate_expression(*expr, env).await
+            }
+            ExpressionKind::Template { quasis, expressions } => {
+                let mut result = quasis[0].clone();
+                for (expression, quasi) in expressions.into_iter().zip(&quasis[1..]) {
+                    let value = self.evaluate_expression(expression, env.clone()).await?;
+                    result.push_str(&coercion::to_js_string(&value));
+                    result.push_str(quasi);
+                }
+                Ok(ZanoValue::String(result))
+            }
+            ExpressionKind::TaggedTemplate { tag, cooked, raw, expressions } => {
+                let description = Self::describe_expression(&tag);
+                let function = self.evaluate_expression(*tag, env.clone()).await?;
+                
+                // The tag gets the cooked strings, with the raw ones as their `raw`
+                // property, followed by the substitution values
+                let strings = ZanoValue::new_array(
+                    cooked.into_iter().map(|text| text.map_or(ZanoValue::Undefined, ZanoValue::String)).collect(),
+                );
+                let raw = ZanoValue::new_array(raw.into_iter().map(ZanoValue::String).collect());
+                self.set_property(&strings, &ZanoValue::String("raw".to_string()), raw)?;
+                
+                let mut arg_values = vec![strings];
+                for expression in expressions {
+                    arg_values.push(self.evaluate_expression(expression, env.clone()).await?);
+                }
+                
+                match function {
+                    ZanoValue::Function(func) => {
+                        self.call_function(&func, ZanoValue::Undefined, arg_values, description, span).await
+                    }
+                    _ => Err(errors::type_error(format!("{} is not a function", description))),
+                }
+            }
+            ExpressionKind::New { callee, args } => {
+                let description = Self::describe_expression(&callee);
+                let constructor = self.evaluate_expression(*callee, env.clone()).await?;
+                
+                let arg_values = self.evaluate_elements(args, env.clone()).await?;
+                
+                match constructor {
+                    ZanoValue::Function(func) if func.is_constructor() => {
+                        self.call_function(&func, ZanoValue::Undefined, arg_values, description, span).await
+                    }
+                    _ => Err(errors::type_error(format!("{} is not a constructor", description))),
+                }
             }
         }
+        }.await;
+        result.map_err(|error| self.locate(error, span))
         })
     }
     
-    fn apply_binary_operator(&self, left: ZanoValue, op: BinaryOp, right: ZanoValue) -> Result<ZanoValue> {
-        match (left, right) {
-            (ZanoValue::Number(a), ZanoValue::Number(b)) => {
-                let result = match op {
-                    BinaryOp::Add => a + b,
-                    BinaryOp::Sub => a - b,
-                    BinaryOp::Mul => a * b,
-                    BinaryOp::Div => a / b,
-                    BinaryOp::Mod => a % b,
-                    BinaryOp::Equal => return Ok(ZanoValue::Boolean(a == b)),
-                    BinaryOp::NotEqual => return Ok(ZanoValue::Boolean(a != b)),
-                    BinaryOp::Less => return Ok(ZanoValue::Boolean(a < b)),
-                    BinaryOp::Greater => return Ok(ZanoValue::Boolean(a > b)),
-                    BinaryOp::LessEqual => return Ok(ZanoValue::Boolean(a <= b)),
-                    BinaryOp::GreaterEqual => return Ok(ZanoValue::Boolean(a >= b)),
-                    _ => return Err(anyhow::anyhow!("Invalid operation for numbers")),
+    /// Evaluates a member, index or call expression along with the links of
+    /// the chain below it. `None` means a `?.` found null or undefined, which
+    /// skips the rest of the chain.
+    fn evaluate_chain(&self, expression: Expression, env: Arc<Environment>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Option<ZanoValue>>> + Send + '_>> {
+        Box::pin(async move {
+        let span = expression.span;
+        let result: Result<Option<ZanoValue>> = async move {
+        let expression = Self::check_stack(expression)?;
+        match expression.kind {
+            ExpressionKind::Member { object, property, optional } => {
+                let Some(object) = self.evaluate_link(*object, optional, env).await? else {
+                    return Ok(None);
                 };
-                Ok(ZanoValue::Number(result))
+                self.get_property(&object, &ZanoValue::String(property)).map(Some)
             }
-            (ZanoValue::String(a), ZanoValue::String(b)) => {
-                match op {
-                    BinaryOp::Add => Ok(ZanoValue::String(format!("{}{}", a, b))),
-                    BinaryOp::Equal => Ok(ZanoValue::Boolean(a == b)),
-                    BinaryOp::NotEqual => Ok(ZanoValue::Boolean(a != b)),
-                    _ => Err(anyhow::anyhow!("Invalid operation for strings")),
-                }
+            ExpressionKind::Index { object, index, optional } => {
+                let Some(object) = self.evaluate_link(*object, optional, env.clone()).await? else {
+                    return Ok(None);
+                };
+                let key = self.evaluate_expression(*index, env).await?;
+                self.get_property(&object, &key).map(Some)
             }
-            (ZanoValue::String(a), ZanoValue::Number(b)) => {
-                match op {
-                    BinaryOp::Add => Ok(ZanoValue::String(format!("{}{}", a, b))),
-                    _ => Err(anyhow::anyhow!("Invalid operation for string and number")),
+            ExpressionKind::Call { callee, args, optional } => {
+                let description = Self::describe_expression(&callee);
+                let Some((function, this)) = self.evaluate_callee(*callee, env.clone()).await? else {
+                    return Ok(None);
+                };
+                if optional && matches!(function, ZanoValue::Null | ZanoValue::Undefined) {
+                    return Ok(None);
+                }
+                
+                let arg_values = self.evaluate_elements(args, env.clone()).await?;
+                
+                match function {
+                    ZanoValue::Function(func) => self.call_function(&func, this, arg_values, description, span).await.map(Some),
+                    _ => Err(errors::type_error(format!("{} is not a function", description))),
                 }
             }
-            (ZanoValue::Number(a), ZanoValue::String(b)) => {
-                match op {
-                    BinaryOp::Add => Ok(ZanoValue::String(format!("{}{}", a, b))),
-                    _ => Err(anyhow::anyhow!("Invalid operation for number and string")),
+            kind => self.evaluate_expression(Expression::new(kind, span), env).await.map(Some),
+        }
+        }.await;
+        result.map_err(|error| self.locate(error, span))
+        })
+    }
+    
+    // Evaluates call arguments or array elements, expanding any spreads
+    async fn evaluate_elements(&self, elements: Vec<Expression>, env: Arc<Environment>) -> Result<Vec<ZanoValue>> {
+        let mut values = Vec::new();
+        for element in elements {
+            let span = element.span;
+            match element.kind {
+                ExpressionKind::Spread(iterable) => {
+                    let description = Self::describe_expression(&iterable);
+                    let iterable = self.evaluate_expression(*iterable, env.clone()).await?;
+                    values.extend(Self::iterate(&iterable, &description).map_err(|error| self.locate(error, span))?);
                 }
+                kind => values.push(self.evaluate_expression(Expression::new(kind, span), env.clone()).await?),
+            }
+        }
+        Ok(values)
+    }
+    
+    // The values `for...of` and spreading visit: array items, or a string's characters
+    fn iterate(iterable: &ZanoValue, description: &str) -> Result<Vec<ZanoValue>> {
+        match iterable {
+            ZanoValue::Array(array) => Ok(array.read().values()),
+            ZanoValue::String(s) => Ok(s.chars().map(|c| ZanoValue::String(c.to_string())).collect()),
+            _ => Err(errors::type_error(format!("{} is not iterable", description))),
+        }
+    }
+    
+    // The properties `{...value}` copies. Primitives other than strings have none.
+    fn own_properties(value: &ZanoValue) -> Vec<(String, ZanoValue)> {
+        match value {
+            ZanoValue::Object(object) => object.read().properties.clone().into_iter().collect(),
+            ZanoValue::Array(array) => {
+                let array = array.read();
+                let items = array.entries().map(|(index, item)| (index.to_string(), item.clone()));
+                items.chain(array.properties.clone()).collect()
+            }
+            // Indexed by UTF-16 code unit, as `s[i]` is
+            ZanoValue::String(s) => s
+                .encode_utf16()
+                .enumerate()
+                .map(|(index, unit)| (index.to_string(), ZanoValue::String(String::from_utf16_lossy(&[unit]))))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+    
+    // The function a call link calls and the `this` it gets: the object when
+    // the callee is a property access, otherwise undefined. `None` if the
+    // chain stopped before reaching the function.
+    async fn evaluate_callee(&self, callee: Expression, env: Arc<Environment>) -> Result<Option<(ZanoValue, ZanoValue)>> {
+        let span = callee.span;
+        let (object, key) = match callee.kind {
+            ExpressionKind::Member { object, property, optional } => {
+                let Some(object) = self.evaluate_link(*object, optional, env).await? else {
+                    return Ok(None);
+                };
+                (object, ZanoValue::String(property))
             }
-            (ZanoValue::Boolean(a), ZanoValue::Boolean(b)) => {
-                let result = match op {
-                    BinaryOp::And => a && b,
-                    BinaryOp::Or => a || b,
-                    BinaryOp::Equal => a == b,
-                    BinaryOp::NotEqual => a != b,
-                    _ => return Err(anyhow::anyhow!("Invalid operation for booleans")),
+            ExpressionKind::Index { object, index, optional } => {
+                let Some(object) = self.evaluate_link(*object, optional, env.clone()).await? else {
+                    return Ok(None);
+                };
+                let key = self.evaluate_expression(*index, env).await?;
+                (object, key)
+            }
+            kind => {
+                let function = self.evaluate_chain(Expression::new(kind, span), env).await?;
+                return Ok(function.map(|function| (function, ZanoValue::Undefined)));
+            }
+        };
+        let function = self.get_property(&object, &key).map_err(|error| self.locate(error, span))?;
+        Ok(Some((function, object)))
+    }
+    
+    // The object or callee of a chain link, or `None` if the chain stops here
+    async fn evaluate_link(&self, expression: Expression, optional: bool, env: Arc<Environment>) -> Result<Option<ZanoValue>> {
+        match self.evaluate_chain(expression, env).await? {
+            Some(ZanoValue::Null | ZanoValue::Undefined) if optional => Ok(None),
+            value => Ok(value),
+        }
+    }
+    
+    async fn evaluate_unary(&self, operator: UnaryOp, operand: Expression, env: Arc<Environment>) -> Result<ZanoValue> {
+        match operator {
+            UnaryOp::Typeof => {
+                // typeof on an undeclared name is "undefined" rather than an error
+                let value = match operand.kind {
+                    ExpressionKind::Identifier(name) => env.get(&name).await.unwrap_or(ZanoValue::Undefined),
+                    kind => self.evaluate_expression(Expression::new(kind, operand.span), env).await?,
                 };
-                Ok(ZanoValue::Boolean(result))
+                Ok(ZanoValue::String(coercion::type_of(&value).to_string()))
+            }
+            UnaryOp::Delete => self.delete_property(operand, env).await,
+            _ => {
+                let value = self.evaluate_expression(operand, env).await?;
+                Ok(match operator {
+                    UnaryOp::Not => ZanoValue::Boolean(!self.is_truthy(&value)),
+                    UnaryOp::Negate => match coercion::to_numeric(&value) {
+                        ZanoValue::BigInt(n) => ZanoValue::BigInt(-n),
+                        number => ZanoValue::Number(-coercion::to_number(&number)),
+                    },
+                    UnaryOp::BitNot => match coercion::to_numeric(&value) {
+                        ZanoValue::BigInt(n) => ZanoValue::BigInt(!n),
+                        number => ZanoValue::Number(!coercion::to_int32(coercion::to_number(&number)) as f64),
+                    },
+                    UnaryOp::Plus => match coercion::to_numeric(&value) {
+                        ZanoValue::BigInt(_) => {
+                            return Err(errors::type_error("Cannot convert a BigInt value to a number"));
+                        }
+                        number => number,
+                    },
+                    _ => ZanoValue::Undefined,
+                })
+            }
+        }
+    }
+    
+    // Removes a property named by a member or index expression
+    async fn delete_property(&self, target: Expression, env: Arc<Environment>) -> Result<ZanoValue> {
+        let (object, key) = match target.kind {
+            ExpressionKind::Member { object, property, .. } => (*object, ZanoValue::String(property)),
+            ExpressionKind::Index { object, index, .. } => {
+                let key = self.evaluate_expression(*index, env.clone()).await?;
+                (*object, key)
+            }
+            kind => {
+                // Deleting anything other than a property is a no-op
+                self.evaluate_expression(Expression::new(kind, target.span), env).await?;
+                return Ok(ZanoValue::Boolean(true));
             }
-            _ => Err(anyhow::anyhow!("Type mismatch in binary operation")),
+        };
+        
+        match self.evaluate_expression(object, env).await? {
+            // Keys are converted before taking the lock, as converting one
+            // may read the very object being changed
+            ZanoValue::Object(object) => {
+                let name = coercion::to_property_key(&key);
+                object.write().properties.shift_remove(&name);
+            }
+            ZanoValue::Array(array) => {
+                // Deleting an element leaves a hole rather than shifting the array
+                match Self::property_key(&key) {
+                    PropertyKey::Index(index) => array.write().delete(index),
+                    PropertyKey::Name(name) => {
+                        array.write().properties.shift_remove(&name);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(ZanoValue::Boolean(true))
+    }
+    
+    fn apply_binary_operator(&self, left: ZanoValue, op: BinaryOp, right: ZanoValue) -> Result<ZanoValue> {
+        let result = match op {
+            BinaryOp::Add => {
+                // Concatenate if either side is (or converts to) a string
+                let (left, right) = (coercion::to_primitive(&left), coercion::to_primitive(&right));
+                if matches!(left, ZanoValue::String(_)) || matches!(right, ZanoValue::String(_)) {
+                    ZanoValue::String(coercion::to_js_string(&left) + &coercion::to_js_string(&right))
+                } else {
+                    Self::arithmetic(op, &left, &right)?
+                }
+            }
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod | BinaryOp::Pow
+            | BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor
+            | BinaryOp::ShiftLeft | BinaryOp::ShiftRight | BinaryOp::UnsignedShiftRight => {
+                Self::arithmetic(op, &left, &right)?
+            }
+            BinaryOp::Equal => ZanoValue::Boolean(coercion::loose_equals(&left, &right)),
+            BinaryOp::NotEqual => ZanoValue::Boolean(!coercion::loose_equals(&left, &right)),
+            BinaryOp::StrictEqual => ZanoValue::Boolean(coercion::strict_equals(&left, &right)),
+            BinaryOp::StrictNotEqual => ZanoValue::Boolean(!coercion::strict_equals(&left, &right)),
+            BinaryOp::Less => ZanoValue::Boolean(coercion::compare(&left, &right) == Some(Ordering::Less)),
+            BinaryOp::Greater => ZanoValue::Boolean(coercion::compare(&left, &right) == Some(Ordering::Greater)),
+            BinaryOp::LessEqual => {
+                ZanoValue::Boolean(matches!(coercion::compare(&left, &right), Some(Ordering::Less | Ordering::Equal)))
+            }
+            BinaryOp::GreaterEqual => {
+                ZanoValue::Boolean(matches!(coercion::compare(&left, &right), Some(Ordering::Greater | Ordering::Equal)))
+            }
+            BinaryOp::Instanceof => match &right {
+                ZanoValue::Function(function) => ZanoValue::Boolean(function.has_instance(&left)),
+                _ => return Err(errors::type_error("Right-hand side of 'instanceof' is not callable")),
+            },
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Nullish => {
+                return Err(anyhow::anyhow!("Logical operators are evaluated before reaching here"));
+            }
+        };
+        Ok(result)
+    }
+    
+    // The numeric operators, which work on two Numbers or two BigInts but
+    // never a mix of both
+    fn arithmetic(op: BinaryOp, left: &ZanoValue, right: &ZanoValue) -> Result<ZanoValue> {
+        let (x, y) = match (coercion::to_numeric(left), coercion::to_numeric(right)) {
+            (ZanoValue::BigInt(x), ZanoValue::BigInt(y)) => return Self::bigint_arithmetic(op, x, y).map(ZanoValue::BigInt),
+            (ZanoValue::BigInt(_), _) | (_, ZanoValue::BigInt(_)) => {
+                return Err(errors::type_error("Cannot mix BigInt and other types, use explicit conversions"));
+            }
+            (x, y) => (coercion::to_number(&x), coercion::to_number(&y)),
+        };
+        
+        // The bitwise operators work on 32-bit integers, and shift counts
+        // only use their low five bits
+        let shift = coercion::to_uint32(y) & 31;
+        Ok(ZanoValue::Number(match op {
+            BinaryOp::Add => x + y,
+            BinaryOp::Sub => x - y,
+            BinaryOp::Mul => x * y,
+            BinaryOp::Div => x / y,
+            BinaryOp::Mod => x % y,
+            BinaryOp::BitAnd => (coercion::to_int32(x) & coercion::to_int32(y)) as f64,
+            BinaryOp::BitOr => (coercion::to_int32(x) | coercion::to_int32(y)) as f64,
+            BinaryOp::BitXor => (coercion::to_int32(x) ^ coercion::to_int32(y)) as f64,
+            BinaryOp::ShiftLeft => coercion::to_int32(x).wrapping_shl(shift) as f64,
+            BinaryOp::ShiftRight => (coercion::to_int32(x) >> shift) as f64,
+            BinaryOp::UnsignedShiftRight => (coercion::to_uint32(x) >> shift) as f64,
+            _ => coercion::exponentiate(x, y),
+        }))
+    }
+    
+    // The largest BigInt a left shift or `**` may produce, as in V8
+    const MAX_BIGINT_BITS: usize = 1 << 30;
+    
+    // Integer arithmetic: division truncates, and the remainder takes the
+    // sign of the dividend
+    fn bigint_arithmetic(op: BinaryOp, x: BigInt, y: BigInt) -> Result<BigInt> {
+        match op {
+            BinaryOp::Add => Ok(x + y),
+            BinaryOp::Sub => Ok(x - y),
+            BinaryOp::Mul => Ok(x * y),
+            BinaryOp::Div | BinaryOp::Mod if y.is_zero() => Err(errors::range_error("Division by zero")),
+            BinaryOp::Div => Ok(x / y),
+            BinaryOp::Mod => Ok(x % y),
+            BinaryOp::BitAnd => Ok(x & y),
+            BinaryOp::BitOr => Ok(x | y),
+            BinaryOp::BitXor => Ok(x ^ y),
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                // A negative count shifts the other way; right shifts round down
+                let left = (op == BinaryOp::ShiftLeft) != y.is_negative();
+                match (y.abs().to_usize(), left) {
+                    (Some(count), true) if count <= Self::MAX_BIGINT_BITS => Ok(x << count),
+                    (Some(count), false) => Ok(x >> count),
+                    (_, false) => Ok(if x.is_negative() { BigInt::from(-1) } else { BigInt::zero() }),
+                    (_, true) if x.is_zero() => Ok(x),
+                    (_, true) => Err(errors::range_error("Maximum BigInt size exceeded")),
+                }
+            }
+            BinaryOp::UnsignedShiftRight => {
+                Err(errors::type_error("BigInts have no unsigned right shift, use >> instead"))
+            }
+            _ if y.is_negative() => Err(errors::range_error("Exponent must be non-negative")),
+            _ => match y.to_u32() {
+                // Each factor of |x| >= 2 adds at least bits(x) - 1 bits
+                Some(exponent) if x.bits().saturating_sub(1).saturating_mul(exponent as u64) > Self::MAX_BIGINT_BITS as u64 => {
+                    Err(errors::range_error("Maximum BigInt size exceeded"))
+                }
+                Some(exponent) => Ok(x.pow(exponent)),
+                // Only 0, 1 and -1 stay small enough to hold
+                None if x.abs() <= BigInt::from(1) => Ok(if x.is_negative() && y.bit(0) { x } else { x.abs() }),
+                None => Err(errors::range_error("Maximum BigInt size exceeded")),
+            },
+        }
+    }
+    
+    // Evaluates the parts of an assignment target without reading its value
+    async fn resolve_reference(&self, target: Expression, env: Arc<Environment>) -> Result<Reference> {
+        match target.kind {
+            ExpressionKind::Identifier(name) => Ok(Reference::Binding(name)),
+            ExpressionKind::Member { object, property, .. } => {
+                let object = self.evaluate_expression(*object, env).await?;
+                Ok(Reference::Property(object, ZanoValue::String(property)))
+            }
+            ExpressionKind::Index { object, index, .. } => {
+                let object = self.evaluate_expression(*object, env.clone()).await?;
+                let key = self.evaluate_expression(*index, env).await?;
+                Ok(Reference::Property(object, key))
+            }
+            _ => Err(errors::syntax_error("Invalid left-hand side in assignment")),
+        }
+    }
+    
+    async fn get_value(&self, reference: &Reference, env: &Arc<Environment>) -> Result<ZanoValue> {
+        match reference {
+            Reference::Binding(name) => env
+                .get(name)
+                .await
+                .ok_or_else(|| errors::reference_error(format!("Undefined variable: {}", name))),
+            Reference::Property(object, key) => self.get_property(object, key),
+        }
+    }
+    
+    async fn put_value(&self, reference: &Reference, value: ZanoValue, env: &Arc<Environment>) -> Result<()> {
+        match reference {
+            Reference::Binding(name) => {
+                env.assign(name, value).await
+            }
+            Reference::Property(object, key) => self.set_property(object, key, value),
+        }
+    }
+    
+    // Reads `object[key]`, converting the key the way property access does
+    fn get_property(&self, object: &ZanoValue, key: &ZanoValue) -> Result<ZanoValue> {
+        let value = match object {
+            ZanoValue::Object(object) => {
+                let name = coercion::to_property_key(key);
+                object.read().properties.get(&name).cloned().unwrap_or(ZanoValue::Undefined)
+            }
+            ZanoValue::Array(array) => match Self::property_key(key) {
+                PropertyKey::Index(index) => array.read().get(index).cloned().unwrap_or(ZanoValue::Undefined),
+                PropertyKey::Name(name) => match name.as_str() {
+                    "length" => ZanoValue::Number(array.read().length() as f64),
+                    name => array.read().properties.get(name).cloned().unwrap_or(ZanoValue::Undefined),
+                },
+            },
+            ZanoValue::String(s) => match Self::property_key(key) {
+                PropertyKey::Index(index) => s
+                    .encode_utf16()
+                    .nth(index)
+                    .map(|unit| ZanoValue::String(String::from_utf16_lossy(&[unit])))
+                    .unwrap_or(ZanoValue::Undefined),
+                PropertyKey::Name(name) if name == "length" => {
+                    ZanoValue::Number(s.encode_utf16().count() as f64)
+                }
+                PropertyKey::Name(_) => ZanoValue::Undefined,
+            },
+            ZanoValue::Null | ZanoValue::Undefined => {
+                return Err(errors::type_error(format!(
+                    "Cannot read properties of {} (reading '{}')",
+                    coercion::to_js_string(object),
+                    coercion::to_property_key(key)
+                )));
+            }
+            _ => ZanoValue::Undefined,
+        };
+        Ok(value)
+    }
+    
+    // The longest array, well short of JavaScript's 2^32 - 1 so that
+    // spreading or joining one can't exhaust memory
+    const MAX_ARRAY_LENGTH: usize = 1 << 24;
+    
+    // Writes `object[key] = value`. Objects gain new properties and arrays
+    // grow to fit the index.
+    fn set_property(&self, object: &ZanoValue, key: &ZanoValue, value: ZanoValue) -> Result<()> {
+        match object {
+            // The key and value are converted before taking the lock, as
+            // converting them may read the very object being written
+            ZanoValue::Object(object) => {
+                let name = coercion::to_property_key(key);
+                object.write().properties.insert(name, value);
+            }
+            ZanoValue::Array(array) => match Self::property_key(key) {
+                PropertyKey::Index(index) => {
+                    if index >= Self::MAX_ARRAY_LENGTH {
+                        return Err(errors::range_error("Invalid array length"));
+                    }
+                    array.write().set(index, value);
+                }
+                PropertyKey::Name(name) => {
+                    if name == "length" {
+                        let length = coercion::to_number(&value);
+                        if length < 0.0 || length.fract() != 0.0 || length > Self::MAX_ARRAY_LENGTH as f64 {
+                            return Err(errors::range_error("Invalid array length"));
+                        }
+                        array.write().set_length(length as usize);
+                    } else {
+                        array.write().properties.insert(name, value);
+                    }
+                }
+            }
+            ZanoValue::Null | ZanoValue::Undefined => {
+                return Err(errors::type_error(format!(
+                    "Cannot set properties of {} (setting '{}')",
+                    coercion::to_js_string(object),
+                    coercion::to_property_key(key)
+                )));
+            }
+            // Writes to primitives are silently discarded
+            _ => {}
+        }
+        Ok(())
+    }
+    
+    // Converts a key the way arrays and strings see it. An index is a
+    // non-negative integer below 2^32 - 1, or a key whose string form spells one.
+    fn property_key(key: &ZanoValue) -> PropertyKey {
+        if let ZanoValue::Number(n) = key {
+            if *n >= 0.0 && n.fract() == 0.0 && *n < u32::MAX as f64 {
+                return PropertyKey::Index(*n as usize);
+            }
+        }
+        let name = coercion::to_property_key(key);
+        match name.parse::<u32>() {
+            Ok(index) if index < u32::MAX && index.to_string() == name => PropertyKey::Index(index as usize),
+            _ => PropertyKey::Name(name),
+        }
+    }
+    
+    // Renders an expression back to source form for runtime error messages
+    fn describe_expression(expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::Member { object, property, .. } => {
+                format!("{}.{}", Self::describe_expression(object), property)
+            }
+            _ => "expression".to_string(),
         }
     }
     
@@ -328,50 +1278,141 @@ impl ZanoRuntime {
         match value {
             ZanoValue::Boolean(b) => *b,
             ZanoValue::Null | ZanoValue::Undefined => false,
-            ZanoValue::Number(n) => *n != 0.0,
+            ZanoValue::Number(n) => *n != 0.0 && !n.is_nan(),
+            ZanoValue::BigInt(n) => !n.is_zero(),
             ZanoValue::String(s) => !s.is_empty(),
             _ => true,
         }
     }
 }
 
+/// The target of an assignment: a variable binding or a property on a value.
+enum Reference {
+    Binding(String),
+    Property(ZanoValue, ZanoValue),
+}
+
+/// A property key converted for an array or string: an element index, or
+/// the name of any other property.
+enum PropertyKey {
+    Index(usize),
+    Name(String),
+}
+
 impl Clone for ZanoRuntime {
     fn clone(&self) -> Self {
         Self {
             globals: self.globals.clone(),
-            functions: self.functions.clone(),
             modules: self.modules.clone(),
+            call_stack: self.call_stack.clone(),
+            file_name: self.file_name.clone(),
         }
     }
 }
 
 struct UserDefinedFunction {
-    params: Vec<String>,
+    name: String,
+    params: Vec<Parameter>,
     body: Vec<Statement>,
+    closure: Arc<Environment>,
     runtime: ZanoRuntime,
+    // Arrow functions have no `this` of their own and see their closure's
+    is_arrow: bool,
 }
 
 #[async_trait]
 impl ZanoFunction for UserDefinedFunction {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
+        self.call_with_this(ZanoValue::Undefined, args).await
+    }
+    
+    async fn call_with_this(&self, this: ZanoValue, args: Vec<ZanoValue>) -> Result<ZanoValue> {
         // Create new scope for function execution
-        let function_runtime = self.runtime.clone();
+        let function_env = Environment::function(&self.closure);
+        if !self.is_arrow {
+            // `this` is a keyword, so the binding cannot clash with a variable
+            function_env.define("this".to_string(), this).await;
+            // An array stands in for the array-like `arguments` object
+            function_env.define("arguments".to_string(), ZanoValue::new_array(args.clone())).await;
+        }
         
-        // Bind parameters
-        for (i, param) in self.params.iter().enumerate() {
-            let value = args.get(i).cloned().unwrap_or(ZanoValue::Undefined);
-            function_runtime.globals.write().await.insert(param.clone(), value);
+        // Bind parameters in order, so defaults can refer to earlier ones
+        let mut args = args.into_iter();
+        for param in &self.params {
+            let value = if param.rest {
+                ZanoValue::new_array(args.by_ref().collect())
+            } else {
+                args.next().unwrap_or(ZanoValue::Undefined)
+            };
+            let value = self.runtime.apply_default(&param.default, value, &function_env).await?;
+            self.runtime.bind_pattern(&param.target, value, &function_env, Some(DeclarationKind::Let)).await?;
         }
         
-        // Execute function body
-        function_runtime.execute(self.body.clone()).await
+        // Execute function body; falling off the end returns undefined
+        self.runtime.hoist(&self.body, &function_env).await;
+        match self.runtime.execute_block(self.body.clone(), function_env).await? {
+            Completion::Return(value) => Ok(value),
+            _ => Ok(ZanoValue::Undefined),
+        }
+    }
+    
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// `Error`, `TypeError` and the other built-in error constructors. Called
+/// with or without `new`, they take a message and an options object whose
+/// `cause` is kept on the error.
+struct ErrorConstructor {
+    error_type: ErrorType,
+    runtime: ZanoRuntime,
+}
+
+#[async_trait]
+impl ZanoFunction for ErrorConstructor {
+    async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
+        let message = match args.first() {
+            None | Some(ZanoValue::Undefined) => String::new(),
+            Some(message) => coercion::to_js_string(message),
+        };
+        let cause = match args.get(1) {
+            Some(ZanoValue::Object(options)) => options.read().properties.get("cause").cloned(),
+            _ => None,
+        };
+        
+        // The innermost frame is this call, so the stack starts where it was made
+        let mut frames = self.runtime.frames();
+        let location = frames.pop().map(|frame| frame.call_site).unwrap_or_default();
+        Ok(self.runtime.create_error(self.error_type, message, cause, location, &frames))
+    }
+    
+    fn name(&self) -> &str {
+        self.error_type.name()
+    }
+    
+    fn is_constructor(&self) -> bool {
+        true
+    }
+    
+    // Every error type counts as an instance of Error
+    fn has_instance(&self, value: &ZanoValue) -> bool {
+        let ZanoValue::Object(object) = value else {
+            return false;
+        };
+        match object.read().error_type {
+            Some(error_type) => self.error_type == ErrorType::Error || self.error_type == error_type,
+            None => false,
+        }
     }
 }
 
+#[allow(dead_code)]
 struct BuiltinFunction<F> {
     func: F,
 }
 
+#[allow(dead_code)]
 impl<F> BuiltinFunction<F>
 where
     F: Fn(Vec<ZanoValue>) -> Result<ZanoValue> + Send + Sync,
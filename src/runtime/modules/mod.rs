@@ -1,7 +1,9 @@
 use crate::parser::ZanoValue;
+use crate::runtime::coercion::{self, number_to_string};
 use crate::runtime::{ZanoFunction, ZanoRuntime};
 use anyhow::Result;
 use async_trait::async_trait;
+use indexmap::IndexMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -44,93 +46,93 @@ impl ModuleSystem {
     }
     
     fn create_console_module(&self) -> ZanoValue {
-        let mut console_obj = HashMap::new();
+        let mut console_obj = IndexMap::new();
         
         // console.log
         console_obj.insert(
             "log".to_string(),
-            ZanoValue::Function("console_log".to_string()),
+            ZanoValue::Function(Arc::new(ConsoleLog)),
         );
         
         // console.error
         console_obj.insert(
             "error".to_string(),
-            ZanoValue::Function("console_error".to_string()),
+            ZanoValue::Function(Arc::new(ConsoleError)),
         );
         
         // console.warn
         console_obj.insert(
             "warn".to_string(),
-            ZanoValue::Function("console_warn".to_string()),
+            ZanoValue::Function(Arc::new(ConsoleWarn)),
         );
         
-        ZanoValue::Object(console_obj)
+        ZanoValue::new_object(console_obj)
     }
     
     fn create_fs_module(&self) -> ZanoValue {
-        let mut fs_obj = HashMap::new();
+        let mut fs_obj = IndexMap::new();
         
         // fs.readFile
         fs_obj.insert(
             "readFile".to_string(),
-            ZanoValue::Function("fs_read_file".to_string()),
+            ZanoValue::Function(Arc::new(FsReadFile)),
         );
         
         // fs.writeFile
         fs_obj.insert(
             "writeFile".to_string(),
-            ZanoValue::Function("fs_write_file".to_string()),
+            ZanoValue::Function(Arc::new(FsWriteFile)),
         );
         
         // fs.exists
         fs_obj.insert(
             "exists".to_string(),
-            ZanoValue::Function("fs_exists".to_string()),
+            ZanoValue::Function(Arc::new(FsExists)),
         );
         
-        ZanoValue::Object(fs_obj)
+        ZanoValue::new_object(fs_obj)
     }
     
     fn create_http_module(&self) -> ZanoValue {
-        let mut http_obj = HashMap::new();
+        let mut http_obj = IndexMap::new();
         
         // http.createServer
         http_obj.insert(
             "createServer".to_string(),
-            ZanoValue::Function("http_create_server".to_string()),
+            ZanoValue::Function(Arc::new(HttpCreateServer)),
         );
         
         // http.request
         http_obj.insert(
             "request".to_string(),
-            ZanoValue::Function("http_request".to_string()),
+            ZanoValue::Function(Arc::new(HttpRequest)),
         );
         
-        ZanoValue::Object(http_obj)
+        ZanoValue::new_object(http_obj)
     }
     
     fn create_path_module(&self) -> ZanoValue {
-        let mut path_obj = HashMap::new();
+        let mut path_obj = IndexMap::new();
         
         // path.join
         path_obj.insert(
             "join".to_string(),
-            ZanoValue::Function("path_join".to_string()),
+            ZanoValue::Function(Arc::new(PathJoin)),
         );
         
         // path.dirname
         path_obj.insert(
             "dirname".to_string(),
-            ZanoValue::Function("path_dirname".to_string()),
+            ZanoValue::Function(Arc::new(PathDirname)),
         );
         
         // path.basename
         path_obj.insert(
             "basename".to_string(),
-            ZanoValue::Function("path_basename".to_string()),
+            ZanoValue::Function(Arc::new(PathBasename)),
         );
         
-        ZanoValue::Object(path_obj)
+        ZanoValue::new_object(path_obj)
     }
 }
 
@@ -140,30 +142,68 @@ pub struct ConsoleLog;
 #[async_trait]
 impl ZanoFunction for ConsoleLog {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
-        let messages: Vec<String> = args.iter().map(|arg| zano_value_to_string(arg)).collect();
+        let messages: Vec<String> = args.iter().map(zano_value_to_string).collect();
         println!("{}", messages.join(" "));
         Ok(ZanoValue::Undefined)
     }
 }
 
 fn zano_value_to_string(value: &ZanoValue) -> String {
+    format_value(value, &mut Vec::new())
+}
+
+// `seen` holds the arrays and objects being printed further up; a
+// reference back to one prints as [Circular]
+fn format_value(value: &ZanoValue, seen: &mut Vec<*const ()>) -> String {
     match value {
         ZanoValue::String(s) => s.clone(),
-        ZanoValue::Number(n) => n.to_string(),
+        ZanoValue::Number(n) => number_to_string(*n),
+        ZanoValue::BigInt(n) => format!("{}n", n),
         ZanoValue::Boolean(b) => b.to_string(),
         ZanoValue::Null => "null".to_string(),
         ZanoValue::Undefined => "undefined".to_string(),
+        ZanoValue::Array(arr) if seen.contains(&arr.as_ptr()) => "[Circular]".to_string(),
         ZanoValue::Array(arr) => {
-            let items: Vec<String> = arr.iter().map(zano_value_to_string).collect();
+            // Runs of holes print as a count, as Node does
+            seen.push(arr.as_ptr());
+            let array = arr.read();
+            let mut items = Vec::new();
+            let mut next = 0;
+            for (index, item) in array.entries() {
+                if index > next {
+                    items.push(empty_items(index - next));
+                }
+                items.push(format_value(item, seen));
+                next = index + 1;
+            }
+            if array.length() > next {
+                items.push(empty_items(array.length() - next));
+            }
+            seen.pop();
             format!("[{}]", items.join(", "))
         },
+        // Errors print their stack, as Node does
+        ZanoValue::Object(obj) if obj.read().error_type.is_some() => match obj.read().properties.get("stack") {
+            Some(ZanoValue::String(stack)) => stack.clone(),
+            _ => coercion::to_js_string(value),
+        },
+        ZanoValue::Object(obj) if seen.contains(&obj.as_ptr()) => "[Circular]".to_string(),
         ZanoValue::Object(obj) => {
-            let items: Vec<String> = obj.iter().map(|(k, v)| {
-                format!("{}: {}", k, zano_value_to_string(v))
+            seen.push(obj.as_ptr());
+            let items: Vec<String> = obj.read().properties.iter().map(|(k, v)| {
+                format!("{}: {}", k, format_value(v, seen))
             }).collect();
+            seen.pop();
             format!("{{{}}}", items.join(", "))
         },
-        ZanoValue::Function(name) => format!("function {}", name),
+        ZanoValue::Function(func) => format!("function {}", func.name()),
+    }
+}
+
+fn empty_items(count: usize) -> String {
+    match count {
+        1 => "<1 empty item>".to_string(),
+        count => format!("<{} empty items>", count),
     }
 }
 
@@ -172,7 +212,7 @@ pub struct ConsoleError;
 #[async_trait]
 impl ZanoFunction for ConsoleError {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
-        let messages: Vec<String> = args.iter().map(|arg| zano_value_to_string(arg)).collect();
+        let messages: Vec<String> = args.iter().map(zano_value_to_string).collect();
         eprintln!("{}", messages.join(" "));
         Ok(ZanoValue::Undefined)
     }
@@ -183,7 +223,7 @@ pub struct ConsoleWarn;
 #[async_trait]
 impl ZanoFunction for ConsoleWarn {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
-        let messages: Vec<String> = args.iter().map(|arg| zano_value_to_string(arg)).collect();
+        let messages: Vec<String> = args.iter().map(zano_value_to_string).collect();
         println!("WARN: {}", messages.join(" "));
         Ok(ZanoValue::Undefined)
     }
@@ -212,7 +252,7 @@ impl ZanoFunction for FsWriteFile {
     async fn call(&self, args: Vec<ZanoValue>) -> Result<ZanoValue> {
         if args.len() >= 2 {
             if let (Some(ZanoValue::String(path)), Some(ZanoValue::String(content))) = 
-                (args.get(0), args.get(1)) {
+                (args.first(), args.get(1)) {
                 match tokio::fs::write(path, content).await {
                     Ok(_) => Ok(ZanoValue::Undefined),
                     Err(e) => Err(anyhow::anyhow!("Failed to write file: {}", e)),
@@ -0,0 +1,45 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn gaps_are_holes_however_far_the_write() {
+    let source = "\
+let a = []
+a[3] = 1
+let b = []
+b[2000] = 1
+let keys = []
+for (let k in a) keys[keys.length] = k
+for (let k in b) keys[keys.length] = k
+console.log(a, b, keys)
+console.log(a[0], b[0], a.length, b.length)
+";
+    assert_eq!(
+        output(source),
+        "[<3 empty items>, 1] [<2000 empty items>, 1] [3, 2000]\nundefined undefined 4 2001\n"
+    );
+}
+
+#[test]
+fn delete_and_length_leave_holes() {
+    let source = "\
+let a = [1, 2, 3]
+delete a[1]
+a.length = 5
+console.log(a, [...a], a + \"\")
+";
+    assert_eq!(output(source), "[1, <1 empty item>, 3, <2 empty items>] [1, undefined, 3, undefined, undefined] 1,,3,,\n");
+}
+
+#[test]
+fn far_writes_stay_cheap() {
+    let source = "\
+let a = []
+a[1000000] = 1
+console.log(a.length, a[1000000])
+try { a[1e9] = 1 } catch (e) { console.log(e.name, e.message) }
+try { a.length = 4294967295 } catch (e) { console.log(e.name, e.message) }
+";
+    assert_eq!(output(source), "1000001 1\nRangeError Invalid array length\nRangeError Invalid array length\n");
+}
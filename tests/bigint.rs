@@ -0,0 +1,21 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn huge_exponent_throws_range_error() {
+    let source = "\
+try { 10n ** 4000000000n } catch (e) { console.log(e.name, e.message) }
+try { 10n ** 400000000n } catch (e) { console.log(e.name, e.message) }
+";
+    assert_eq!(
+        output(source),
+        "RangeError Maximum BigInt size exceeded\nRangeError Maximum BigInt size exceeded\n"
+    );
+}
+
+#[test]
+fn small_bases_take_any_exponent() {
+    let source = "console.log(2n ** 64n, 0n ** 5000000000n, 1n ** 4000000000n, (-1n) ** 4000000001n)\n";
+    assert_eq!(output(source), "18446744073709551616n 0n 1n -1n\n");
+}
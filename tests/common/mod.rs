@@ -0,0 +1,47 @@
+//! Runs scripts through the `zano` binary, the way a user would.
+
+use std::fs;
+use std::process::Command;
+
+pub struct Run {
+    pub stdout: String,
+    pub stderr: String,
+    pub success: bool,
+}
+
+/// Runs `source` as `script.zn` from a scratch directory, so error
+/// locations read `script.zn:line:column`.
+pub fn run(source: &str) -> Run {
+    let dir = tempfile::tempdir().expect("create scratch directory");
+    fs::write(dir.path().join("script.zn"), source).expect("write script");
+    let output = Command::new(env!("CARGO_BIN_EXE_zano"))
+        .arg("script.zn")
+        .current_dir(dir.path())
+        .output()
+        .expect("run zano");
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        success: output.status.success(),
+    }
+}
+
+/// Runs a script expected to succeed and returns what it printed.
+#[allow(dead_code)]
+pub fn output(source: &str) -> String {
+    let run = run(source);
+    assert!(run.success, "script failed:\n{}", run.stderr);
+    run.stdout
+}
+
+/// The `--> file:line:column` locations of the errors a script reported.
+#[allow(dead_code)]
+pub fn error_locations(source: &str) -> Vec<String> {
+    let run = run(source);
+    assert!(!run.success, "script unexpectedly succeeded:\n{}", run.stdout);
+    run.stderr
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("--> "))
+        .map(str::to_string)
+        .collect()
+}
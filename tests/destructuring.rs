@@ -0,0 +1,22 @@
+mod common;
+
+use common::{error_locations, output};
+
+#[test]
+fn for_heads_assign_to_members_and_elements() {
+    let source = "\
+let o = {}
+let arr = []
+let i = 0
+for (o.last of [1, 2]) {}
+for (arr[i] in {x: 1, y: 2}) i++
+for ([o.a, arr[5]] of [[7, 8]]) {}
+console.log(o, arr[0], arr[1], arr[5])
+";
+    assert_eq!(output(source), "{last: 2, a: 7} x y 8\n");
+}
+
+#[test]
+fn for_heads_reject_other_expressions() {
+    assert_eq!(error_locations("for (f() of [1]) {}\nfor (a?.b in {}) {}\n"), ["script.zn:1:6", "script.zn:2:6"]);
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn instanceof_matches_error_types() {
+    let source = "\
+try { null.x } catch (e) { console.log(e instanceof TypeError, e instanceof Error, e instanceof RangeError) }
+const renamed = new RangeError(\"r\")
+renamed.name = \"Other\"
+console.log(renamed instanceof RangeError, {} instanceof Error, \"Error\" instanceof Error)
+";
+    assert_eq!(output(source), "true true false\ntrue false false\n");
+}
+
+#[test]
+fn instanceof_needs_a_function() {
+    let source = "try { ({}) instanceof 5 } catch (e) { console.log(e.name, e.message) }\n";
+    assert_eq!(output(source), "TypeError Right-hand side of 'instanceof' is not callable\n");
+}
@@ -0,0 +1,18 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn nan_and_infinity_are_constant() {
+    let source = "\
+try { NaN = 1 } catch (e) { console.log(e.name, e.message) }
+try { Infinity += 1 } catch (e) { console.log(e.name, e.message) }
+console.log(NaN, Infinity, NaN === NaN)
+";
+    assert_eq!(
+        output(source),
+        "TypeError Assignment to constant variable 'NaN'\n\
+         TypeError Assignment to constant variable 'Infinity'\n\
+         NaN Infinity false\n"
+    );
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn var_is_undefined_before_its_declaration() {
+    assert_eq!(output("function f() { return y; var y = 1 }\nconsole.log(f())\n"), "undefined\n");
+}
+
+#[test]
+fn var_in_nested_blocks_is_function_scoped() {
+    let source = "\
+function f() {
+  if (true) { var a = 1 }
+  for (var i = 0; i < 2; i++) {}
+  try { var t = 3 } finally {}
+  return [a, i, t]
+}
+console.log(f())
+";
+    assert_eq!(output(source), "[1, 2, 3]\n");
+}
+
+#[test]
+fn functions_can_be_called_before_their_declaration() {
+    let source = "\
+console.log(g())
+function g() { return inner() + 1; function inner() { return 1 } }
+{
+  console.log(h())
+  function h() { return \"block\" }
+}
+";
+    assert_eq!(output(source), "2\nblock\n");
+}
+
+#[test]
+fn declaration_keeps_the_hoisted_function() {
+    let source = "let saved = f\nfunction f() {}\nconsole.log(saved === f)\n";
+    assert_eq!(output(source), "true\n");
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use common::error_locations;
+
+#[test]
+fn crlf_in_a_template_counts_as_one_line() {
+    let source = "let t = `a\r\nb`\r\nlet x = )\r\n";
+    assert_eq!(error_locations(source), ["script.zn:3:9"]);
+}
+
+#[test]
+fn line_continuation_over_crlf_counts_the_line() {
+    let source = "let s = \"a\\\r\nb\"\r\nlet x = )\r\n";
+    assert_eq!(error_locations(source), ["script.zn:3:9"]);
+}
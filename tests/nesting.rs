@@ -0,0 +1,74 @@
+mod common;
+
+use common::{output, run};
+
+#[test]
+fn nested_parentheses_parse() {
+    let depth = 2000;
+    let source = format!(
+        "const f = {}(x) => x{}\nconsole.log(f(1), {}2{})\n",
+        "(".repeat(depth),
+        ")".repeat(depth),
+        "(".repeat(depth),
+        ")".repeat(depth)
+    );
+    assert_eq!(output(&source), "1 2\n");
+}
+
+#[test]
+fn deeply_nested_parentheses_are_a_syntax_error() {
+    let depth = 300_000;
+    let source = format!("console.log({}1{})\n", "(".repeat(depth), ")".repeat(depth));
+    let run = run(&source);
+    assert!(!run.success);
+    assert!(run.stderr.contains("Error[E0015]: Code is nested too deeply"), "{}", run.stderr);
+}
+
+#[test]
+fn deeply_nested_blocks_throw_range_error() {
+    let depth = 100_000;
+    let source = format!(
+        "try {{\n{}{}}} catch (e) {{ console.log(e.name, e.message) }}\n",
+        "{\n".repeat(depth),
+        "}\n".repeat(depth)
+    );
+    assert_eq!(output(&source), "RangeError Maximum call stack size exceeded\n");
+}
+
+#[test]
+fn recursion_through_nested_statements_throws_range_error() {
+    let source = "\
+function f(n) {
+    try {
+        for (let i = 0; i < 1; i++) {
+            switch (n % 2) {
+                case 0:
+                case 1:
+                    if (n >= 0) {
+                        while (true) {
+                            do {
+                                try {
+                                    for (let j = 0; j < 1; j++) {
+                                        switch (n % 3) {
+                                            default:
+                                                if (n >= 0) {
+                                                    while (true) {
+                                                        do {
+                                                            return f(n + 1)
+                                                        } while (false)
+                                                    }
+                                                }
+                                        }
+                                    }
+                                } finally {}
+                            } while (false)
+                        }
+                    }
+            }
+        }
+    } finally {}
+}
+try { f(0) } catch (e) { console.log(e.name, e.message) }
+";
+    assert_eq!(output(source), "RangeError Maximum call stack size exceeded\n");
+}
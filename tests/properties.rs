@@ -0,0 +1,41 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn an_object_can_be_its_own_key() {
+    let source = "\
+let o = {}
+o[o] = 1
+console.log(o[o], o[\"[object Object]\"])
+delete o[o]
+console.log(o)
+let a = [1]
+a[a] = 2
+console.log(a)
+let b = [0, 1]
+delete b[[1]]
+b[b] = 3
+console.log(b, b[\"0,\"])
+";
+    assert_eq!(output(source), "1 1\n{}\n[1, 2]\n[0, <1 empty item>] 3\n");
+}
+
+#[test]
+fn reserved_words_name_properties() {
+    let source = "\
+let m = { default: 1, new: 2, case: 3, switch: 4, do: 5, delete: 6, typeof: 7, void: 8, for: 9, in: 10, this: 11 }
+console.log(m.default, m.new, m.case, m.switch, m.do, m.delete, m.typeof, m.void, m.for, m.in, m.this)
+let { default: d, new: n } = m
+m.default = 20
+console.log(d, n, m?.default, m[\"new\"])
+";
+    assert_eq!(output(source), "1 2 3 4 5 6 7 8 9 10 11\n1 2 20 2\n");
+}
+
+#[test]
+fn reserved_words_are_not_shorthand_properties() {
+    let run = common::run("let o = { default }\n");
+    assert!(!run.success);
+    assert!(run.stderr.contains("Expected ':' after property name"), "{}", run.stderr);
+}
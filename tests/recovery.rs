@@ -0,0 +1,44 @@
+mod common;
+
+use common::error_locations;
+
+#[test]
+fn reports_every_error_on_one_line() {
+    let source = "let a = (1 + ; let b = ); function f( { } console.log(1\n";
+    assert_eq!(
+        error_locations(source),
+        ["script.zn:1:14", "script.zn:1:24", "script.zn:1:43", "script.zn:1:56"]
+    );
+}
+
+#[test]
+fn skips_the_rest_of_a_multiline_literal() {
+    let source = "\
+let o = {
+  a: 1,
+  b: ,
+  c: 3
+}
+let ok = 1
+let bad = )
+";
+    assert_eq!(error_locations(source), ["script.zn:3:6", "script.zn:7:11"]);
+}
+
+#[test]
+fn resumes_at_a_statement_keyword() {
+    let source = "\
+function g() {
+  let x = (1 +
+  return 5
+}
+let y = ]
+";
+    assert_eq!(error_locations(source), ["script.zn:2:15", "script.zn:5:9"]);
+}
+
+#[test]
+fn semicolons_in_a_for_head_do_not_resynchronize() {
+    let source = "for (let i = 0; i < ; i++) {\n  console.log(i)\n}\n";
+    assert_eq!(error_locations(source), ["script.zn:1:21"]);
+}
@@ -0,0 +1,15 @@
+mod common;
+
+use common::output;
+
+#[test]
+fn for_in_visits_utf16_indices() {
+    let source = "let s = \"a😀\"\nlet keys = []\nfor (let k in s) keys[keys.length] = k\nconsole.log(keys, s.length)\n";
+    assert_eq!(output(source), "[0, 1, 2] 3\n");
+}
+
+#[test]
+fn spread_string_keys_match_indexing() {
+    let source = "let s = \"a😀b\"\nlet o = {...s}\nconsole.log(o[3], o[3] === s[3], o[1] === s[1])\n";
+    assert_eq!(output(source), "b true true\n");
+}